@@ -1,6 +1,6 @@
 use embedded_graphics_core::pixelcolor::{Bgr888, Rgb888, RgbColor};
 
-#[derive(Copy, Clone, Default, Debug)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
 pub struct Accumulator(i16, i16, i16);
 
 impl Accumulator {
@@ -24,6 +24,14 @@ impl core::ops::AddAssign for Accumulator {
     }
 }
 
+impl core::ops::Add for Accumulator {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0 + rhs.0, self.1 + rhs.1, self.2 + rhs.2)
+    }
+}
+
 impl core::ops::Sub for Accumulator {
     type Output = Self;
 
@@ -0,0 +1,536 @@
+use crate::{
+    accumulator::Accumulator, diffusion::Diffusion, wrapping_vec::WrappingVec, DitherError,
+    QuantizationError,
+};
+use core::{marker::PhantomData, ops::Range};
+use embedded_graphics_core::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Point, Size},
+    pixelcolor::{PixelColor, RgbColor},
+    primitives::Rectangle,
+    Pixel,
+};
+
+// draws go to a full colour framebuffer the size of the display, in any order and overlapping as they like
+// (text over shapes over images), nothing reaching the display until flush dithers the whole frame
+// the framebuffer is the caller's, width * height colours in raster order, WIDTH at least the display's width
+// anything drawn outside the display is clipped
+//
+// what changed since the last flush is tracked, and only the rows that could dither differently are sent:
+// error never flows up, so rows above a change are left alone, but it keeps flowing right and down,
+// so the rows changed are sent in full along with the settle rows under them
+// error arriving at the changed rows has to be right too, so the error row at the start of each band sent
+// is kept, and the next flush carries on from the deepest one still above everything changed since,
+// only dithering from the top when there is none
+pub struct BufferedDitherTarget<'a, Display, C, F, const WIDTH: usize>
+where
+    F: Fn(C) -> (Display::Color, QuantizationError),
+    Display: DrawTarget + OriginDimensions,
+    C: PixelColor + From<Accumulator> + RgbColor,
+{
+    display: &'a mut Display,
+    closest_color_fn: &'a F,
+    framebuffer: &'a mut [C],
+    diffusion: Diffusion<WrappingVec<Accumulator, WIDTH>>,
+    dirty: heapless::Vec<Rectangle, DIRTY>,
+    // error owed at the start of each band last sent, by then unchanged above it
+    checkpoints: heapless::Vec<Diffusion<WrappingVec<Accumulator, WIDTH>>, DIRTY>,
+    settle: usize,
+    phantom: PhantomData<C>,
+}
+
+// most separate dirty rectangles kept before they're merged into one
+pub const DIRTY: usize = 4;
+
+// rows under a change that are sent too, by default
+// error from a change fades as it spreads, but never completely: rows past the settle rows keep what was
+// last sent to them, so there can be a faint seam where the band ends, which usize::MAX trades for
+// sending everything below a change
+pub const SETTLE: usize = 8;
+
+// smallest rectangle covering both
+fn envelope(a: &Rectangle, b: &Rectangle) -> Rectangle {
+    let (ax, ay, bx, by) = (a.columns(), a.rows(), b.columns(), b.rows());
+
+    Rectangle::with_corners(
+        Point::new(ax.start.min(bx.start), ay.start.min(by.start)),
+        Point::new(ax.end.max(bx.end) - 1, ay.end.max(by.end) - 1),
+    )
+}
+
+impl<'a, Display, C, F, const WIDTH: usize> BufferedDitherTarget<'a, Display, C, F, WIDTH>
+where
+    F: Fn(C) -> (Display::Color, QuantizationError),
+    Display: DrawTarget + OriginDimensions,
+    C: PixelColor + From<Accumulator> + Into<Accumulator> + RgbColor,
+{
+    pub fn new(
+        display: &'a mut Display,
+        closest_color_fn: &'a F,
+        framebuffer: &'a mut [C],
+    ) -> Result<Self, DitherError<Display::Error>> {
+        const {
+            assert!(
+                WIDTH > 0,
+                "the error buffer needs to be at least one pixel wide"
+            )
+        };
+
+        let size = display.size();
+        if size.width as usize > WIDTH
+            || framebuffer.len() != size.width as usize * size.height as usize
+        {
+            return Err(DitherError::SizeMismatch);
+        }
+
+        // nothing has been sent yet, so all of it
+        let mut dirty = heapless::Vec::new();
+        if size.width > 0 && size.height > 0 {
+            dirty.push(Rectangle::new(Point::zero(), size)).ok();
+        }
+
+        Ok(Self {
+            display,
+            closest_color_fn,
+            framebuffer,
+            diffusion: Diffusion::new(WrappingVec::new(&mut core::iter::empty())),
+            dirty,
+            checkpoints: heapless::Vec::new(),
+            settle: SETTLE,
+            phantom: PhantomData,
+        })
+    }
+
+    // how many rows under a change are sent as well, more meaning fainter seams under it (see SETTLE)
+    pub fn set_settle(&mut self, rows: usize) {
+        self.settle = rows;
+    }
+
+    // what has changed since the last flush
+    pub fn dirty(&self) -> &[Rectangle] {
+        &self.dirty
+    }
+
+    // marks an area as needing sending whether it has changed or not
+    pub fn invalidate(&mut self, area: &Rectangle) {
+        let area = area.intersection(&Rectangle::new(Point::zero(), self.display.size()));
+        if area.is_zero_sized() {
+            return;
+        }
+
+        if self.dirty.push(area).is_err() {
+            let all = self
+                .dirty
+                .iter()
+                .fold(area, |all, area| envelope(&all, area));
+            self.dirty.clear();
+            self.dirty.push(all).ok();
+        }
+    }
+
+    // bands of rows to send for what is dirty, in order and not overlapping
+    fn bands(&self) -> heapless::Vec<Range<usize>, DIRTY> {
+        let height = self.display.size().height as usize;
+
+        let mut rows: heapless::Vec<Range<usize>, DIRTY> = self
+            .dirty
+            .iter()
+            .map(|area| {
+                let rows = area.rows();
+                rows.start as usize..height.min((rows.end as usize).saturating_add(self.settle))
+            })
+            .collect();
+        rows.sort_unstable_by_key(|rows| rows.start);
+
+        let mut bands: heapless::Vec<Range<usize>, DIRTY> = heapless::Vec::new();
+        for rows in rows {
+            match bands.last_mut() {
+                Some(last) if rows.start <= last.end => last.end = last.end.max(rows.end),
+                _ => bands.push(rows).unwrap(),
+            }
+        }
+
+        bands
+    }
+
+    // dithers the frame as it stands and sends whatever could have changed on the display,
+    // returning the areas sent
+    pub fn flush(
+        &mut self,
+    ) -> Result<heapless::Vec<Rectangle, DIRTY>, DitherError<Display::Error>> {
+        let width = self.display.size().width as usize;
+        let bands = self.bands();
+
+        let mut sent = heapless::Vec::new();
+        if bands.is_empty() {
+            return Ok(sent);
+        }
+
+        // nothing above the first band has changed, so the error owed at any row up to it still holds
+        let start = bands[0].start * width;
+        match self
+            .checkpoints
+            .iter()
+            .filter(|checkpoint| checkpoint.position() <= start)
+            .max_by_key(|checkpoint| checkpoint.position())
+        {
+            Some(checkpoint) => self.diffusion = checkpoint.clone(),
+            None => self.diffusion.restart(Point::zero(), width),
+        }
+
+        let mut checkpoints = heapless::Vec::new();
+        let mut row = self.diffusion.position() / width;
+        for band in bands {
+            // rows above are dithered again only for the error they pass down
+            for color in &self.framebuffer[row * width..band.start * width] {
+                self.diffusion.diffuse(*color, self.closest_color_fn);
+            }
+            checkpoints.push(self.diffusion.clone()).ok();
+
+            let area = Rectangle::new(
+                Point::new(0, band.start as i32),
+                Size::new(width as u32, band.len() as u32),
+            );
+            self.diffusion.fill_contiguous(
+                self.display,
+                self.closest_color_fn,
+                &area,
+                self.framebuffer[band.start * width..band.end * width]
+                    .iter()
+                    .copied(),
+            )?;
+            sent.push(area).ok();
+            row = band.end;
+        }
+        // only once sent, so a display error leaves it all to send again
+        self.dirty.clear();
+        self.checkpoints = checkpoints;
+
+        Ok(sent)
+    }
+
+    // index into the framebuffer, if on the display
+    fn index(&self, point: Point) -> Option<usize> {
+        let size = self.display.size();
+        let x = u32::try_from(point.x).ok().filter(|x| *x < size.width)?;
+        let y = u32::try_from(point.y).ok().filter(|y| *y < size.height)?;
+
+        Some(y as usize * size.width as usize + x as usize)
+    }
+}
+
+impl<'a, Display, C, F, const WIDTH: usize> DrawTarget
+    for BufferedDitherTarget<'a, Display, C, F, WIDTH>
+where
+    F: Fn(C) -> (Display::Color, QuantizationError),
+    Display: DrawTarget + OriginDimensions,
+    C: PixelColor + From<Accumulator> + RgbColor,
+{
+    type Color = C;
+    type Error = DitherError<Display::Error>;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        // corners of what actually changed
+        let mut changed: Option<(Point, Point)> = None;
+        for Pixel(point, color) in pixels {
+            let Some(index) = self.index(point) else {
+                continue;
+            };
+            if self.framebuffer[index] == color {
+                continue;
+            }
+            self.framebuffer[index] = color;
+
+            changed = Some(match changed {
+                None => (point, point),
+                Some((min, max)) => (min.component_min(point), max.component_max(point)),
+            });
+        }
+
+        if let Some((min, max)) = changed {
+            self.invalidate(&Rectangle::with_corners(min, max));
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a, Display, C, F, const WIDTH: usize> OriginDimensions
+    for BufferedDitherTarget<'a, Display, C, F, WIDTH>
+where
+    F: Fn(C) -> (Display::Color, QuantizationError),
+    Display: DrawTarget + OriginDimensions,
+    C: PixelColor + From<Accumulator> + RgbColor,
+{
+    fn size(&self) -> Size {
+        self.display.size()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{test_util::BLACK_WHITE, DitherTarget};
+    use embedded_graphics::{
+        mock_display::MockDisplay,
+        pixelcolor::Rgb888,
+        prelude::*,
+        primitives::{Circle, PrimitiveStyle, Rectangle},
+    };
+
+    // a grey background with a lighter circle over it and a dark bar over both, drawn out of order
+    fn compose<D>(target: &mut D)
+    where
+        D: DrawTarget<Color = Rgb888>,
+        D::Error: core::fmt::Debug,
+    {
+        target
+            .fill_solid(&target.bounding_box(), Rgb888::new(0x60, 0x60, 0x60))
+            .unwrap();
+        Circle::new(Point::new(10, 10), 30)
+            .into_styled(PrimitiveStyle::with_fill(Rgb888::new(0xc0, 0xc0, 0xc0)))
+            .draw(target)
+            .unwrap();
+        Rectangle::new(Point::new(-5, 20), Size::new(80, 6))
+            .into_styled(PrimitiveStyle::with_fill(Rgb888::new(0x20, 0x20, 0x20)))
+            .draw(target)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_flush() {
+        let closest = |color| BLACK_WHITE.with_error(color);
+
+        // the same as composing in full colour first and dithering that as an image
+        let mut frame = MockDisplay::<Rgb888>::new();
+        frame.set_allow_overdraw(true);
+        frame.set_allow_out_of_bounds_drawing(true);
+        compose(&mut frame);
+        let mut expected = MockDisplay::<Rgb888>::new();
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut expected, &closest);
+        target
+            .fill_contiguous(
+                &frame.bounding_box(),
+                frame
+                    .bounding_box()
+                    .points()
+                    .map(|point| frame.get_pixel(point).unwrap()),
+            )
+            .unwrap();
+
+        let mut display = MockDisplay::<Rgb888>::new();
+        let mut framebuffer = [Rgb888::BLACK; 64 * 64];
+        let mut target: BufferedDitherTarget<'_, _, Rgb888, _, 64> =
+            BufferedDitherTarget::new(&mut display, &closest, &mut framebuffer).unwrap();
+        compose(&mut target);
+        target.flush().unwrap();
+
+        display.assert_eq(&expected);
+    }
+
+    #[test]
+    fn test_nothing_until_flush() {
+        let closest = |color| BLACK_WHITE.with_error(color);
+        let mut display = MockDisplay::<Rgb888>::new();
+        let mut framebuffer = [Rgb888::BLACK; 64 * 64];
+        let mut target: BufferedDitherTarget<'_, _, Rgb888, _, 64> =
+            BufferedDitherTarget::new(&mut display, &closest, &mut framebuffer).unwrap();
+
+        compose(&mut target);
+        assert_eq!(display, MockDisplay::new());
+
+        let mut framebuffer = [Rgb888::BLACK; 64 * 63];
+        assert!(matches!(
+            BufferedDitherTarget::<'_, _, Rgb888, _, 64>::new(
+                &mut display,
+                &closest,
+                &mut framebuffer
+            ),
+            Err(DitherError::SizeMismatch)
+        ));
+        let mut framebuffer = [Rgb888::BLACK; 64 * 64];
+        assert!(matches!(
+            BufferedDitherTarget::<'_, _, Rgb888, _, 32>::new(
+                &mut display,
+                &closest,
+                &mut framebuffer
+            ),
+            Err(DitherError::SizeMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_dirty() {
+        let closest = |color| BLACK_WHITE.with_error(color);
+        let mut display = MockDisplay::<Rgb888>::new();
+        display.set_allow_overdraw(true);
+        let mut framebuffer = [Rgb888::BLACK; 64 * 64];
+        let mut target: BufferedDitherTarget<'_, _, Rgb888, _, 64> =
+            BufferedDitherTarget::new(&mut display, &closest, &mut framebuffer).unwrap();
+
+        // everything to begin with
+        assert_eq!(target.dirty(), [target.bounding_box()]);
+        compose(&mut target);
+        assert_eq!(target.flush().unwrap(), [target.bounding_box()]);
+        assert!(target.dirty().is_empty());
+        assert!(target.flush().unwrap().is_empty());
+
+        // drawing what's already there changes nothing
+        Rectangle::new(Point::new(0, 20), Size::new(64, 6))
+            .into_styled(PrimitiveStyle::with_fill(Rgb888::new(0x20, 0x20, 0x20)))
+            .draw(&mut target)
+            .unwrap();
+        assert!(target.dirty().is_empty());
+
+        // two small changes, sent as the rows they're on and the settle rows below
+        let label = Rectangle::new(Point::new(30, 4), Size::new(10, 3));
+        label
+            .into_styled(PrimitiveStyle::with_fill(Rgb888::WHITE))
+            .draw(&mut target)
+            .unwrap();
+        Pixel(Point::new(5, 50), Rgb888::new(0xf0, 0, 0))
+            .draw(&mut target)
+            .unwrap();
+        assert_eq!(
+            target.dirty(),
+            [label, Rectangle::new(Point::new(5, 50), Size::new(1, 1))]
+        );
+        assert_eq!(
+            target.flush().unwrap(),
+            [
+                Rectangle::new(Point::new(0, 4), Size::new(64, 3 + SETTLE as u32)),
+                Rectangle::new(Point::new(0, 50), Size::new(64, 1 + SETTLE as u32)),
+            ]
+        );
+
+        // overlapping bands go out as one, and with settle rows to the bottom it's exact
+        target.set_settle(usize::MAX);
+        label
+            .into_styled(PrimitiveStyle::with_fill(Rgb888::new(0x90, 0x90, 0x90)))
+            .draw(&mut target)
+            .unwrap();
+        Pixel(Point::new(5, 50), Rgb888::new(0x60, 0x60, 0x60))
+            .draw(&mut target)
+            .unwrap();
+        assert_eq!(
+            target.flush().unwrap(),
+            [Rectangle::new(Point::new(0, 4), Size::new(64, 60))]
+        );
+
+        let mut expected = MockDisplay::<Rgb888>::new();
+        let mut framebuffer = [Rgb888::BLACK; 64 * 64];
+        let mut target: BufferedDitherTarget<'_, _, Rgb888, _, 64> =
+            BufferedDitherTarget::new(&mut expected, &closest, &mut framebuffer).unwrap();
+        compose(&mut target);
+        label
+            .into_styled(PrimitiveStyle::with_fill(Rgb888::new(0x90, 0x90, 0x90)))
+            .draw(&mut target)
+            .unwrap();
+        target.flush().unwrap();
+        display.assert_eq(&expected);
+    }
+
+    #[test]
+    fn test_settle() {
+        use core::cell::Cell;
+
+        let quantized = Cell::new(0);
+        let closest = |color| {
+            quantized.set(quantized.get() + 1);
+            BLACK_WHITE.with_error(color)
+        };
+        let label = Rectangle::new(Point::new(30, 20), Size::new(10, 3));
+        let grey = PrimitiveStyle::with_fill(Rgb888::new(0x90, 0x90, 0x90));
+
+        let mut display = MockDisplay::<Rgb888>::new();
+        display.set_allow_overdraw(true);
+        let mut framebuffer = [Rgb888::BLACK; 64 * 64];
+        let mut target: BufferedDitherTarget<'_, _, Rgb888, _, 64> =
+            BufferedDitherTarget::new(&mut display, &closest, &mut framebuffer).unwrap();
+        compose(&mut target);
+        target.flush().unwrap();
+
+        // the rows above are dithered again once, for the error they pass down
+        label
+            .into_styled(PrimitiveStyle::with_fill(Rgb888::WHITE))
+            .draw(&mut target)
+            .unwrap();
+        quantized.set(0);
+        target.flush().unwrap();
+        assert_eq!(quantized.get(), 64 * (23 + SETTLE));
+
+        // then not at all, the error row at the band's start having been kept
+        label.into_styled(grey).draw(&mut target).unwrap();
+        quantized.set(0);
+        assert_eq!(
+            target.flush().unwrap(),
+            [Rectangle::new(
+                Point::new(0, 20),
+                Size::new(64, 3 + SETTLE as u32)
+            )]
+        );
+        assert_eq!(quantized.get(), 64 * (3 + SETTLE));
+
+        // what was sent is as if the whole frame were dithered again, what's below it as first sent
+        let mut first = MockDisplay::<Rgb888>::new();
+        let mut framebuffer = [Rgb888::BLACK; 64 * 64];
+        let mut target: BufferedDitherTarget<'_, _, Rgb888, _, 64> =
+            BufferedDitherTarget::new(&mut first, &closest, &mut framebuffer).unwrap();
+        compose(&mut target);
+        target.flush().unwrap();
+
+        let mut whole = MockDisplay::<Rgb888>::new();
+        let mut framebuffer = [Rgb888::BLACK; 64 * 64];
+        let mut target: BufferedDitherTarget<'_, _, Rgb888, _, 64> =
+            BufferedDitherTarget::new(&mut whole, &closest, &mut framebuffer).unwrap();
+        compose(&mut target);
+        label.into_styled(grey).draw(&mut target).unwrap();
+        target.flush().unwrap();
+
+        for point in display.bounding_box().points() {
+            let expected = if (20..23 + SETTLE as i32).contains(&point.y) {
+                &whole
+            } else {
+                &first
+            };
+            assert_eq!(display.get_pixel(point), expected.get_pixel(point));
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let closest = |color| BLACK_WHITE.with_error(color);
+        let mut display = MockDisplay::<Rgb888>::new();
+        display.set_allow_overdraw(true);
+        let mut framebuffer = [Rgb888::BLACK; 64 * 64];
+        let mut target: BufferedDitherTarget<'_, _, Rgb888, _, 64> =
+            BufferedDitherTarget::new(&mut display, &closest, &mut framebuffer).unwrap();
+        target.flush().unwrap();
+
+        // one more than can be kept apart, so they're all merged
+        for i in 0..=DIRTY as i32 {
+            Pixel(Point::new(i * 3, i * 10), Rgb888::WHITE)
+                .draw(&mut target)
+                .unwrap();
+        }
+        assert_eq!(
+            target.dirty(),
+            [Rectangle::with_corners(
+                Point::zero(),
+                Point::new(DIRTY as i32 * 3, DIRTY as i32 * 10)
+            )]
+        );
+
+        // and anything off the display doesn't count
+        target.flush().unwrap();
+        target.invalidate(&Rectangle::new(Point::new(70, 70), Size::new(5, 5)));
+        Pixel(Point::new(-1, 3), Rgb888::WHITE)
+            .draw(&mut target)
+            .unwrap();
+        assert!(target.dirty().is_empty());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::{distance, palette::Palette};
 use embedded_graphics_core::{
     draw_target::DrawTarget,
     geometry::OriginDimensions,
@@ -97,6 +98,8 @@ pub const RGB_DISPLAY_PAIRS: [(CGAColor, Rgb888); 16] = [
     (CGAColor::White, Rgb888::new(0xff, 0xff, 0xff)),
 ];
 
+pub const PALETTE: Palette<CGAColor, 16> = Palette::new(RGB_DISPLAY_PAIRS, distance::redmean);
+
 impl PixelColor for CGAColor {
     type Raw = RawU32;
 }
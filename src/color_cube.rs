@@ -1,49 +1,104 @@
-use embedded_graphics_core::pixelcolor::RgbColor;
+use crate::lattice::{cell, sample};
+use crate::palette::Palette;
+use embedded_graphics_core::pixelcolor::{Rgb888, RgbColor};
 use heapless::Entry;
 use heapless::FnvIndexMap;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    // the cube maps to more distinct colours than P
+    TooManyColors,
+}
+
+// R, G and B are how many samples are taken along each axis
+// they needn't match, eye and display are both more sensitive to green than blue
+// so something like 16 x 32 x 8 spends memory where it does the most good
+// P is how many distinct colours the cube can hold, it has to be a power of two
+// 64 is plenty for CGA-ish palettes, xterm's 256 colours need 256
 #[derive(Debug)]
-pub struct ColorCube<C, const N: usize>(
-    [[[C; N]; N]; N],
-    FnvIndexMap<C, ((u64, u64, u64), usize), 64>,
+pub struct ColorCuboid<C, const R: usize, const G: usize, const B: usize, const P: usize = 64>(
+    [[[C; B]; G]; R],
+    FnvIndexMap<C, ((u64, u64, u64), usize), P>,
 )
 where
     C: PartialEq + core::cmp::Eq + core::hash::Hash + Copy + Clone;
 
-impl<C, const N: usize> ColorCube<C, N>
+// the same resolution along every axis
+pub type ColorCube<C, const N: usize, const P: usize = 64> = ColorCuboid<C, N, N, N, P>;
+
+impl<C, const R: usize, const G: usize, const B: usize, const P: usize> ColorCuboid<C, R, G, B, P>
 where
     C: PartialEq + core::cmp::Eq + core::hash::Hash + Copy + Clone,
 {
-    pub fn from<F>(f: &F) -> Result<Self, <u8 as TryFrom<usize>>::Error>
+    pub fn from<F>(f: &F) -> Result<Self, Error>
     where
         Self: Default,
         F: Fn(u8, u8, u8) -> C,
     {
         let mut result: Self = Self::default();
-        for r in 0..N {
-            for g in 0..N {
-                for b in 0..N {
-                    let r_8 = (255 / (N - 1)) as u8 * r as u8;
-                    let g_8 = (255 / (N - 1)) as u8 * g as u8;
-                    let b_8 = (255 / (N - 1)) as u8 * b as u8;
+        for r in 0..R {
+            for g in 0..G {
+                for b in 0..B {
+                    let r_8 = sample(r, R);
+                    let g_8 = sample(g, G);
+                    let b_8 = sample(b, B);
 
                     result.0[r][g][b] = f(r_8, g_8, b_8);
                 }
             }
         }
-        result.approximate_centers();
+        result.approximate_centers()?;
 
         Ok(result)
     }
 
-    pub fn neighbours(&mut self, r: usize, g: usize, b: usize) -> heapless::Vec<&C, 26> {
-        let mut result = heapless::Vec::<&C, 26>::new();
+    // like from, but f also says which rgb the chosen colour really is
+    // quantization error is then measured against that rgb instead of a centroid of cells
+    pub fn from_with_rgb<F>(f: &F) -> Result<Self, Error>
+    where
+        Self: Default,
+        F: Fn(u8, u8, u8) -> (C, Rgb888),
+    {
+        let mut result: Self = Self::default();
+        for r in 0..R {
+            for g in 0..G {
+                for b in 0..B {
+                    let r_8 = sample(r, R);
+                    let g_8 = sample(g, G);
+                    let b_8 = sample(b, B);
+
+                    let (color, rgb) = f(r_8, g_8, b_8);
+                    result.0[r][g][b] = color;
+                    if let Entry::Vacant(v) = result.1.entry(color) {
+                        v.insert(((rgb.r().into(), rgb.g().into(), rgb.b().into()), 0))
+                            .map_err(|_| Error::TooManyColors)?;
+                    }
+                    if let Entry::Occupied(mut o) = result.1.entry(color) {
+                        o.get_mut().1 += 1;
+                    }
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub fn from_palette<const M: usize>(palette: &Palette<C, M>) -> Result<Self, Error>
+    where
+        Self: Default,
+    {
+        Self::from_with_rgb(&|r, g, b| palette.nearest(Rgb888::new(r, g, b)))
+    }
+
+    // cell r, g, b and every cell touching it
+    pub fn neighbours(&self, r: usize, g: usize, b: usize) -> heapless::Vec<&C, 27> {
+        let mut result = heapless::Vec::<&C, 27>::new();
         for i in [-1isize, 0, 1] {
-            if (i + r as isize) < N as isize && (i + r as isize) >= 0 {
+            if (i + r as isize) < R as isize && (i + r as isize) >= 0 {
                 for j in [-1isize, 0, 1] {
-                    if (j + g as isize) < N as isize && (j + g as isize) >= 0 {
+                    if (j + g as isize) < G as isize && (j + g as isize) >= 0 {
                         for k in [-1isize, 0, 1] {
-                            if (k + b as isize) < N as isize && (k + b as isize) >= 0 {
+                            if (k + b as isize) < B as isize && (k + b as isize) >= 0 {
                                 let _ = result.push(
                                     &self.0[(i + r as isize) as usize][(j + g as isize) as usize]
                                         [(k + b as isize) as usize],
@@ -57,17 +112,18 @@ where
         result
     }
 
-    pub fn approximate_centers(&mut self) {
+    // replaces whatever centers there were (including true palette rgb) with centroids
+    pub fn approximate_centers(&mut self) -> Result<(), Error> {
         self.1.clear();
-        for r in 0..N {
-            for g in 0..N {
-                for b in 0..N {
-                    let r_8 = (255 / (N - 1)) as u8 * r as u8;
-                    let g_8 = (255 / (N - 1)) as u8 * g as u8;
-                    let b_8 = (255 / (N - 1)) as u8 * b as u8;
+        for r in 0..R {
+            for g in 0..G {
+                for b in 0..B {
+                    let r_8 = sample(r, R);
+                    let g_8 = sample(g, G);
+                    let b_8 = sample(b, B);
 
                     if let Entry::Vacant(v) = self.1.entry(self.0[r][g][b]) {
-                        v.insert(((0, 0, 0), 0)).unwrap();
+                        v.insert(((0, 0, 0), 0)).map_err(|_| Error::TooManyColors)?;
                     }
                     if let Entry::Occupied(mut o) = self.1.entry(self.0[r][g][b]) {
                         let b = o.get_mut();
@@ -94,6 +150,8 @@ where
                 val.1,
             );
         }
+
+        Ok(())
     }
 
     fn center(&self, color: C) -> (u64, u64, u64) {
@@ -118,9 +176,38 @@ where
             )),
         )
     }
+
+    // nearest cell lookup is only right up to the resolution of the cube
+    // close to a boundary between cells the true nearest colour may be in a neighbouring cell
+    // so re-evaluate distance to every colour in the neighbourhood and take the best of those
+    // most useful with from_palette/from_with_rgb, where centers are true palette rgb
+    pub fn with_error_refined<I, F>(&self, index: I, distance: F) -> (C, crate::QuantizationError)
+    where
+        I: RgbColor,
+        F: Fn(Rgb888, Rgb888) -> u32,
+    {
+        let rgb = Rgb888::new(index.r(), index.g(), index.b());
+        let center_rgb = |color: C| {
+            let center = self.center(color);
+            Rgb888::new(center.0 as u8, center.1 as u8, center.2 as u8)
+        };
+
+        let closest = **self
+            .neighbours(cell(rgb.r(), R), cell(rgb.g(), G), cell(rgb.b(), B))
+            .iter()
+            .min_by_key(|color| distance(rgb, center_rgb(***color)))
+            .unwrap();
+
+        (
+            closest,
+            crate::QuantizationError::from(rgb)
+                - crate::QuantizationError::from(center_rgb(closest)),
+        )
+    }
 }
 
-impl<I, C, const N: usize> core::ops::Index<I> for ColorCube<C, N>
+impl<I, C, const R: usize, const G: usize, const B: usize, const P: usize> core::ops::Index<I>
+    for ColorCuboid<C, R, G, B, P>
 where
     I: RgbColor,
     C: PartialEq + core::cmp::Eq + core::hash::Hash + Copy + Clone,
@@ -128,27 +215,332 @@ where
     type Output = C;
 
     fn index(&self, index: I) -> &Self::Output {
-        &self.0[index.r() as usize / (256 / N)][index.g() as usize / (256 / N)]
-            [index.b() as usize / (256 / N)]
+        &self.0[cell(index.r(), R)][cell(index.g(), G)][cell(index.b(), B)]
     }
 }
 
-impl<C, const N: usize> Default for ColorCube<C, N>
+impl<C, const R: usize, const G: usize, const B: usize, const P: usize> Default
+    for ColorCuboid<C, R, G, B, P>
 where
     C: Default + Copy + PartialEq + core::cmp::Eq + core::hash::Hash + Copy + Clone,
 {
     fn default() -> Self {
-        ColorCube(
-            [[[C::default(); N]; N]; N],
-            FnvIndexMap::<C, ((u64, u64, u64), usize), 64>::new(),
+        const {
+            assert!(
+                R >= 2 && G >= 2 && B >= 2,
+                "a colour cube needs at least 2 samples per axis"
+            )
+        };
+
+        ColorCuboid(
+            [[[C::default(); B]; G]; R],
+            FnvIndexMap::<C, ((u64, u64, u64), usize), P>::new(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Packing {
+    // one index per byte, up to 256 colours
+    Byte,
+    // two indices per byte, high nibble first, up to 16 colours
+    Nibble,
+}
+
+impl Packing {
+    // bytes needed for an n cube
+    pub const fn len(self, n: usize) -> usize {
+        match self {
+            Packing::Byte => n * n * n,
+            Packing::Nibble => (n * n * n).div_ceil(2),
+        }
+    }
+
+    pub const fn max_colors(self) -> usize {
+        match self {
+            Packing::Byte => 256,
+            Packing::Nibble => 16,
+        }
+    }
+}
+
+// palette index of the nearest colour to the i'th sample point of an n cube, in r, g, b order
+fn nearest_at<C, const P: usize>(palette: &Palette<C, P>, n: usize, i: usize) -> u8
+where
+    C: Copy,
+{
+    let (r, g, b) = (i / (n * n), (i / n) % n, i % n);
+
+    palette.nearest_index(Rgb888::new(sample(r, n), sample(g, n), sample(b, n))) as u8
+}
+
+fn packed_at<C, const P: usize>(
+    palette: &Palette<C, P>,
+    n: usize,
+    packing: Packing,
+    byte: usize,
+) -> u8
+where
+    C: Copy,
+{
+    match packing {
+        Packing::Byte => nearest_at(palette, n, byte),
+        Packing::Nibble => {
+            let high = nearest_at(palette, n, 2 * byte);
+            let low = if 2 * byte + 1 < n * n * n {
+                nearest_at(palette, n, 2 * byte + 1)
+            } else {
+                0
+            };
+            (high << 4) | low
+        }
+    }
+}
+
+// palette indices of the nearest colour at every sample point of an n cube
+// this is what IndexedColorCube looks colours up in
+pub fn indices<C, const P: usize>(
+    cells: &mut [u8],
+    n: usize,
+    palette: &Palette<C, P>,
+    packing: Packing,
+) where
+    C: Copy,
+{
+    assert!(P <= packing.max_colors());
+    assert_eq!(cells.len(), packing.len(n));
+
+    for (i, cell) in cells.iter_mut().enumerate() {
+        *cell = packed_at(palette, n, packing, i);
+    }
+}
+
+// writes indices out as rust source for a static, meant to be called from a build script
+// so a cube can be computed on the host (with as slow a metric as you like) and live in flash
+//
+// in build.rs:
+//     let mut source = String::new();
+//     dither::color_cube::write_indices(&mut source, "CGA_CUBE", 16, &dither::cga::PALETTE, Packing::Byte)?;
+//     std::fs::write(Path::new(&env::var("OUT_DIR")?).join("cube.rs"), source)?;
+// and then in the crate:
+//     include!(concat!(env!("OUT_DIR"), "/cube.rs"));
+//     static CUBE: IndexedColorCube<CGAColor, 16, 16> = IndexedColorCube::new(&CGA_CUBE, &cga::PALETTE);
+pub fn write_indices<W, C, const P: usize>(
+    out: &mut W,
+    name: &str,
+    n: usize,
+    palette: &Palette<C, P>,
+    packing: Packing,
+) -> core::fmt::Result
+where
+    W: core::fmt::Write,
+    C: Copy,
+{
+    assert!(P <= packing.max_colors());
+
+    writeln!(out, "pub static {}: [u8; {}] = [", name, packing.len(n))?;
+    for i in 0..packing.len(n) {
+        if i % 16 == 0 {
+            write!(out, "   ")?;
+        }
+        write!(out, " {},", packed_at(palette, n, packing, i))?;
+        if i % 16 == 15 || i + 1 == packing.len(n) {
+            writeln!(out)?;
+        }
+    }
+    writeln!(out, "];")
+}
+
+// a cube of palette indices rather than colours, borrowing both
+// constructible in a const context, so with write_indices the whole thing can be a static
+// with Packing::Nibble a 32 cube is 16 KiB
+#[derive(Debug, Clone, Copy)]
+pub struct IndexedColorCube<'a, C, const N: usize, const P: usize>
+where
+    C: Copy,
+{
+    cells: &'a [u8],
+    palette: &'a Palette<C, P>,
+    packing: Packing,
+}
+
+impl<'a, C, const N: usize, const P: usize> IndexedColorCube<'a, C, N, P>
+where
+    C: Copy,
+{
+    pub const fn new(cells: &'a [u8], palette: &'a Palette<C, P>) -> Self {
+        Self::with_packing(cells, palette, Packing::Byte)
+    }
+
+    pub const fn with_packing(
+        cells: &'a [u8],
+        palette: &'a Palette<C, P>,
+        packing: Packing,
+    ) -> Self {
+        assert!(N >= 2, "a colour cube needs at least 2 samples per axis");
+        assert!(P <= packing.max_colors(), "too many colours for packing");
+        assert!(
+            cells.len() == packing.len(N),
+            "cells are the wrong length for N"
+        );
+
+        Self {
+            cells,
+            palette,
+            packing,
+        }
+    }
+
+    // computes indices at runtime into a caller provided buffer
+    pub fn fill(cells: &'a mut [u8], palette: &'a Palette<C, P>, packing: Packing) -> Self {
+        indices(cells, N, palette, packing);
+
+        Self::with_packing(cells, palette, packing)
+    }
+
+    fn index_of<I>(&self, index: I) -> usize
+    where
+        I: RgbColor,
+    {
+        let i = (cell(index.r(), N) * N + cell(index.g(), N)) * N + cell(index.b(), N);
+
+        match self.packing {
+            Packing::Byte => self.cells[i] as usize,
+            Packing::Nibble if i.is_multiple_of(2) => (self.cells[i / 2] >> 4) as usize,
+            Packing::Nibble => (self.cells[i / 2] & 0x0f) as usize,
+        }
+    }
+
+    // error is against the palette rgb
+    pub fn with_error<I>(&self, index: I) -> (C, crate::QuantizationError)
+    where
+        I: RgbColor,
+    {
+        let (color, rgb) = self.palette.entries()[self.index_of(index)];
+
+        (
+            color,
+            crate::QuantizationError::from(index) - crate::QuantizationError::from(rgb),
         )
     }
 }
 
+impl<'a, I, C, const N: usize, const P: usize> core::ops::Index<I> for IndexedColorCube<'a, C, N, P>
+where
+    I: RgbColor,
+    C: Copy,
+{
+    type Output = C;
+
+    fn index(&self, index: I) -> &Self::Output {
+        &self.palette.entries()[self.index_of(index)].0
+    }
+}
+
+// binary format for an IndexedColorCube, everything little endian
+//
+//  offset  size        field
+//  0       4           magic, "DCUB"
+//  4       1           format version, 1
+//  5       1           packing, 0 for Packing::Byte, 1 for Packing::Nibble
+//  6       2           N, samples per axis
+//  8       2           palette length
+//  10      3 * length  palette rgb, r then g then b
+//  ...     rest        cells, exactly as IndexedColorCube holds them
+//
+// the palette is stored so a blob can be checked against the palette it is loaded with
+const MAGIC: [u8; 4] = *b"DCUB";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownPacking(u8),
+    // N or palette length in the blob differ from the cube being loaded
+    SizeMismatch,
+    // blob was made with a palette whose rgb differ from the one given
+    PaletteMismatch,
+    // blob is shorter or longer than its header says
+    WrongLength,
+    // a cell names a colour past the end of the palette
+    BadIndex,
+}
+
+impl<'a, C, const N: usize, const P: usize> IndexedColorCube<'a, C, N, P>
+where
+    C: Copy,
+{
+    // borrows cells straight out of bytes, nothing is copied
+    pub fn from_bytes(bytes: &'a [u8], palette: &'a Palette<C, P>) -> Result<Self, FormatError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(FormatError::WrongLength);
+        }
+        if bytes[0..4] != MAGIC {
+            return Err(FormatError::BadMagic);
+        }
+        if bytes[4] != VERSION {
+            return Err(FormatError::UnsupportedVersion(bytes[4]));
+        }
+        let packing = match bytes[5] {
+            0 => Packing::Byte,
+            1 => Packing::Nibble,
+            other => return Err(FormatError::UnknownPacking(other)),
+        };
+        let n = u16::from_le_bytes([bytes[6], bytes[7]]) as usize;
+        let palette_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        if n != N || palette_len != P || P > packing.max_colors() {
+            return Err(FormatError::SizeMismatch);
+        }
+
+        let (rgb, cells) = bytes[HEADER_LEN..]
+            .split_at_checked(3 * P)
+            .ok_or(FormatError::WrongLength)?;
+        if cells.len() != packing.len(N) {
+            return Err(FormatError::WrongLength);
+        }
+        for ((_, expected), rgb) in palette.entries().iter().zip(rgb.chunks_exact(3)) {
+            if *expected != Rgb888::new(rgb[0], rgb[1], rgb[2]) {
+                return Err(FormatError::PaletteMismatch);
+            }
+        }
+        // every nibble, the padding one at the end too, is written as a valid index
+        let valid = |index: u8| (index as usize) < P;
+        if !cells.iter().all(|&byte| match packing {
+            Packing::Byte => valid(byte),
+            Packing::Nibble => valid(byte >> 4) && valid(byte & 0x0f),
+        }) {
+            return Err(FormatError::BadIndex);
+        }
+
+        Ok(Self::with_packing(cells, palette, packing))
+    }
+
+    #[cfg(feature = "std")]
+    pub fn save<W>(&self, out: &mut W) -> std::io::Result<()>
+    where
+        W: std::io::Write,
+    {
+        out.write_all(&MAGIC)?;
+        out.write_all(&[
+            VERSION,
+            match self.packing {
+                Packing::Byte => 0,
+                Packing::Nibble => 1,
+            },
+        ])?;
+        out.write_all(&(N as u16).to_le_bytes())?;
+        out.write_all(&(P as u16).to_le_bytes())?;
+        for (_, rgb) in self.palette.entries() {
+            out.write_all(&[rgb.r(), rgb.g(), rgb.b()])?;
+        }
+        out.write_all(self.cells)
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use embedded_graphics::pixelcolor::Rgb888;
-
     extern crate std;
     use super::*;
 
@@ -232,11 +624,303 @@ mod test {
 
         assert_eq!(
             color_cube.with_error(Rgb888::new(0x01, 0x01, 0x01)),
-            (Color::Black, (1, 1, 1))
+            (Color::Black, crate::QuantizationError::new((1, 1, 1)))
         );
         assert_eq!(
             color_cube.with_error(Rgb888::new(0xf0, 0xf0, 0xf0)),
-            (Color::White, (-15, -15, -15))
+            (Color::White, crate::QuantizationError::new((-15, -15, -15)))
+        );
+    }
+
+    #[test]
+    fn test_palette_error() {
+        let palette = Palette::new(
+            [
+                (Color::Black, Rgb888::new(0x00, 0x00, 0x00)),
+                (Color::White, Rgb888::new(0xff, 0xff, 0xff)),
+            ],
+            crate::distance::redmean,
+        );
+        let mut color_cube: ColorCube<Color, 4> = ColorCube::from_palette(&palette).unwrap();
+
+        assert_eq!(
+            color_cube.with_error(Rgb888::new(0x10, 0x10, 0x10)),
+            (Color::Black, crate::QuantizationError::new((16, 16, 16)))
+        );
+        assert_eq!(
+            color_cube.with_error(Rgb888::new(0xf0, 0xf0, 0xf0)),
+            (Color::White, crate::QuantizationError::new((-15, -15, -15)))
+        );
+
+        color_cube.approximate_centers().unwrap();
+        assert_ne!(
+            color_cube.with_error(Rgb888::new(0xf0, 0xf0, 0xf0)),
+            (Color::White, crate::QuantizationError::new((-15, -15, -15)))
+        );
+    }
+
+    #[test]
+    fn test_capacity() {
+        let web_safe = |r: u8, g: u8, b: u8| (r / 51) * 36 + (g / 51) * 6 + b / 51;
+
+        let color_cube: ColorCube<u8, 6, 256> = ColorCube::from(&web_safe).unwrap();
+        assert_eq!(color_cube.1.len(), 216);
+
+        assert_eq!(
+            ColorCube::<u8, 6>::from(&web_safe).unwrap_err(),
+            Error::TooManyColors
+        );
+    }
+
+    #[test]
+    fn test_samples() {
+        for n in 2..=256 {
+            assert_eq!(sample(0, n), 0);
+            assert_eq!(sample(n - 1, n), 255);
+            for i in 0..n {
+                assert_eq!(cell(sample(i, n), n), i);
+            }
+        }
+        assert_eq!(
+            (0..6).map(|i| sample(i, 6)).collect::<std::vec::Vec<_>>(),
+            [0, 51, 102, 153, 204, 255]
+        );
+    }
+
+    // every possible input looks up the cell whose sample is nearest to it
+    fn exhaustive<const N: usize>() {
+        let mut color_cube: ColorCube<(u8, u8, u8), N> = ColorCube::default();
+        for r in 0..N {
+            for g in 0..N {
+                for b in 0..N {
+                    color_cube.0[r][g][b] = (sample(r, N), sample(g, N), sample(b, N));
+                }
+            }
+        }
+        // how far each value is from the sample nearest to it
+        let nearest = |v: u8| (0..N).map(|i| v.abs_diff(sample(i, N))).min().unwrap();
+        let mut distance = [0; 256];
+        for (v, distance) in distance.iter_mut().enumerate() {
+            *distance = nearest(v as u8);
+        }
+
+        for r in 0..=255 {
+            for g in 0..=255 {
+                for b in 0..=255 {
+                    let (r_8, g_8, b_8) = color_cube[Rgb888::new(r, g, b)];
+                    assert_eq!(r.abs_diff(r_8), distance[r as usize]);
+                    assert_eq!(g.abs_diff(g_8), distance[g as usize]);
+                    assert_eq!(b.abs_diff(b_8), distance[b as usize]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "slow, run with --ignored"]
+    fn test_exhaustive_index() {
+        exhaustive::<2>();
+        exhaustive::<6>();
+        exhaustive::<16>();
+        exhaustive::<17>();
+        exhaustive::<32>();
+    }
+
+    #[test]
+    fn test_refined() {
+        let palette = Palette::new(
+            [
+                (Color::Black, Rgb888::new(0x00, 0x00, 0x00)),
+                (Color::White, Rgb888::new(0xff, 0xff, 0xff)),
+            ],
+            crate::distance::euclidean,
+        );
+        let color_cube: ColorCube<Color, 3> = ColorCube::from_palette(&palette).unwrap();
+
+        assert_eq!(color_cube[Rgb888::new(0x70, 0x70, 0x70)], Color::White);
+        assert_eq!(
+            color_cube
+                .with_error_refined(Rgb888::new(0x70, 0x70, 0x70), crate::distance::euclidean),
+            (
+                Color::Black,
+                crate::QuantizationError::new((0x70, 0x70, 0x70))
+            )
+        );
+
+        for v in 0..=255 {
+            let gray = Rgb888::new(v, v, v);
+            assert_eq!(
+                color_cube.with_error_refined(gray, crate::distance::euclidean),
+                palette.with_error(gray)
+            );
+        }
+    }
+
+    const PALETTE: Palette<Color, 3> = Palette::new(
+        [
+            (Color::Black, Rgb888::new(0x00, 0x00, 0x00)),
+            (Color::White, Rgb888::new(0xff, 0xff, 0xff)),
+            (Color::Red, Rgb888::new(0xff, 0x00, 0x00)),
+        ],
+        crate::distance::redmean,
+    );
+
+    static CELLS: [u8; 8] = [0, 0, 0, 1, 2, 2, 1, 1];
+    static CUBE: IndexedColorCube<Color, 2, 3> = IndexedColorCube::new(&CELLS, &PALETTE);
+
+    #[test]
+    fn test_indices() {
+        let mut cells = [0; 8];
+        indices(&mut cells, 2, &PALETTE, Packing::Byte);
+        assert_eq!(cells, CELLS);
+
+        let mut source = std::string::String::new();
+        write_indices(&mut source, "CELLS", 2, &PALETTE, Packing::Byte).unwrap();
+        assert_eq!(
+            source,
+            "pub static CELLS: [u8; 8] = [\n    0, 0, 0, 1, 2, 2, 1, 1,\n];\n"
+        );
+
+        let mut source = std::string::String::new();
+        write_indices(&mut source, "CELLS", 2, &PALETTE, Packing::Nibble).unwrap();
+        assert_eq!(
+            source,
+            "pub static CELLS: [u8; 4] = [\n    0, 1, 34, 17,\n];\n"
+        );
+    }
+
+    #[test]
+    fn test_indexed() {
+        let color_cube: ColorCube<Color, 2> = ColorCube::from_palette(&PALETTE).unwrap();
+
+        for rgb in [
+            Rgb888::new(0x00, 0x00, 0x00),
+            Rgb888::new(0x10, 0xf0, 0x10),
+            Rgb888::new(0xf0, 0x10, 0x10),
+            Rgb888::new(0xf0, 0xf0, 0xf0),
+            Rgb888::new(0x30, 0x80, 0xc0),
+        ] {
+            assert_eq!(CUBE[rgb], color_cube[rgb]);
+            assert_eq!(CUBE.with_error(rgb), color_cube.with_error(rgb));
+        }
+    }
+
+    #[test]
+    fn test_packed() {
+        assert_eq!(Packing::Nibble.len(32), 16 * 1024);
+
+        let mut bytes = [0; 125];
+        let bytes = IndexedColorCube::<Color, 5, 3>::fill(&mut bytes, &PALETTE, Packing::Byte);
+        let mut nibbles = [0; 63];
+        let nibbles =
+            IndexedColorCube::<Color, 5, 3>::fill(&mut nibbles, &PALETTE, Packing::Nibble);
+
+        for r in (0..=255).step_by(15) {
+            for g in (0..=255).step_by(15) {
+                for b in (0..=255).step_by(15) {
+                    let rgb = Rgb888::new(r, g, b);
+                    assert_eq!(bytes[rgb], nibbles[rgb]);
+                    assert_eq!(bytes.with_error(rgb), nibbles.with_error(rgb));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_cuboid() {
+        let color_cuboid: ColorCuboid<Color, 2, 5, 3> =
+            ColorCuboid::from_palette(&PALETTE).unwrap();
+        assert_eq!(color_cuboid.0.len(), 2);
+        assert_eq!(color_cuboid.0[0].len(), 5);
+        assert_eq!(color_cuboid.0[0][0].len(), 3);
+
+        for (rgb, color) in [
+            (Rgb888::new(0x00, 0x00, 0x00), Color::Black),
+            (Rgb888::new(0xff, 0xff, 0xff), Color::White),
+            (Rgb888::new(0xff, 0x00, 0x00), Color::Red),
+            (Rgb888::new(0xff, 0x40, 0x00), Color::Red),
+            (Rgb888::new(0x60, 0x50, 0x40), Color::Black),
+        ] {
+            assert_eq!(color_cuboid[rgb], color);
+            assert_eq!(color_cuboid.with_error(rgb), PALETTE.with_error(rgb));
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_round_trip() {
+        for packing in [Packing::Byte, Packing::Nibble] {
+            let mut cells = [0; 125];
+            let cells = &mut cells[..packing.len(5)];
+            let color_cube = IndexedColorCube::<Color, 5, 3>::fill(cells, &PALETTE, packing);
+
+            let mut bytes = std::vec::Vec::new();
+            color_cube.save(&mut bytes).unwrap();
+            assert_eq!(bytes.len(), HEADER_LEN + 3 * 3 + packing.len(5));
+
+            let loaded = IndexedColorCube::<Color, 5, 3>::from_bytes(&bytes, &PALETTE).unwrap();
+            assert_eq!(loaded.cells, color_cube.cells);
+            assert_eq!(loaded.packing, packing);
+            for rgb in [
+                Rgb888::new(0x00, 0x00, 0x00),
+                Rgb888::new(0xf0, 0x10, 0x10),
+                Rgb888::new(0x30, 0x80, 0xc0),
+            ] {
+                assert_eq!(loaded.with_error(rgb), color_cube.with_error(rgb));
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_load_errors() {
+        let mut cells = [0; 8];
+        let mut bytes = std::vec::Vec::new();
+        IndexedColorCube::<Color, 2, 3>::fill(&mut cells, &PALETTE, Packing::Byte)
+            .save(&mut bytes)
+            .unwrap();
+        let load =
+            |bytes: &[u8]| IndexedColorCube::<Color, 2, 3>::from_bytes(bytes, &PALETTE).err();
+
+        assert_eq!(load(&bytes), None);
+        assert_eq!(load(&bytes[..5]), Some(FormatError::WrongLength));
+        assert_eq!(
+            load(&bytes[..bytes.len() - 1]),
+            Some(FormatError::WrongLength)
+        );
+
+        let mut corrupt = bytes.clone();
+        corrupt[0] = b'X';
+        assert_eq!(load(&corrupt), Some(FormatError::BadMagic));
+
+        let mut corrupt = bytes.clone();
+        corrupt[4] = 2;
+        assert_eq!(load(&corrupt), Some(FormatError::UnsupportedVersion(2)));
+
+        let mut corrupt = bytes.clone();
+        corrupt[HEADER_LEN] = 0x01;
+        assert_eq!(load(&corrupt), Some(FormatError::PaletteMismatch));
+
+        let mut corrupt = bytes.clone();
+        corrupt[HEADER_LEN + 3 * 3 + 5] = 200;
+        assert_eq!(load(&corrupt), Some(FormatError::BadIndex));
+
+        // both halves of a nibble packed byte count
+        let mut cells = [0; 4];
+        let mut nibbles = std::vec::Vec::new();
+        IndexedColorCube::<Color, 2, 3>::fill(&mut cells, &PALETTE, Packing::Nibble)
+            .save(&mut nibbles)
+            .unwrap();
+        assert_eq!(load(&nibbles), None);
+        let last = nibbles.len() - 1;
+        nibbles[last] = (nibbles[last] & 0xf0) | 0x03;
+        assert_eq!(load(&nibbles), Some(FormatError::BadIndex));
+        nibbles[last] = 0xf0;
+        assert_eq!(load(&nibbles), Some(FormatError::BadIndex));
+
+        assert_eq!(
+            IndexedColorCube::<Color, 3, 3>::from_bytes(&bytes, &PALETTE).err(),
+            Some(FormatError::SizeMismatch)
         );
     }
 }
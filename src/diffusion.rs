@@ -0,0 +1,317 @@
+use crate::{
+    accumulator::Accumulator,
+    kernel::{self, FloydSteinberg, Kernel},
+    wrapping_vec::Ring,
+    DitherError, QuantizationError,
+};
+use core::{convert::Infallible, marker::PhantomData};
+use embedded_graphics_core::{
+    draw_target::DrawTarget, geometry::Point, pixelcolor::PixelColor, primitives::Rectangle, Pixel,
+};
+
+// error diffusion (floyd-steinberg unless told otherwise) over a raster of a given width, one pixel at a time
+// only the errors still to be applied are kept: ring[0] is the error owed to the current position,
+// ring[i] to the position i pixels further on, so width+1 cells cover everything up to below-right
+//
+// positions are raster indices (y * width + x) counted from the raster's origin,
+// going forward keeps the error still owed (skipped pixels just contribute none), going back drops it
+// the raster and its error outlive a single draw, so an image drawn in bands dithers as if drawn whole
+pub struct Diffusion<R, K = FloydSteinberg>
+where
+    R: Ring<Accumulator>,
+    K: Kernel,
+{
+    ring: R,
+    origin: Point,
+    width: usize,
+    position: usize,
+    // what the last draw_iter had to work around, if anything
+    issue: Option<DitherError<Infallible>>,
+    kernel: PhantomData<K>,
+}
+
+// a snapshot of the error still owed, to carry on from again later
+impl<R, K> Clone for Diffusion<R, K>
+where
+    R: Ring<Accumulator> + Clone,
+    K: Kernel,
+{
+    fn clone(&self) -> Self {
+        Self {
+            ring: self.ring.clone(),
+            origin: self.origin,
+            width: self.width,
+            position: self.position,
+            issue: self.issue,
+            kernel: PhantomData,
+        }
+    }
+}
+
+impl<R, K> Diffusion<R, K>
+where
+    R: Ring<Accumulator>,
+    K: Kernel,
+{
+    pub fn new(ring: R) -> Self {
+        assert!(ring.capacity() > 1);
+        let width = ring.capacity() - 1;
+
+        Self {
+            ring,
+            origin: Point::zero(),
+            width,
+            position: 0,
+            issue: None,
+            kernel: PhantomData,
+        }
+    }
+
+    // widest raster the ring can diffuse over
+    pub fn max_width(&self) -> usize {
+        self.ring.capacity() - 1
+    }
+
+    // raster index of the next pixel to be diffused
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    // as DitherTarget::issue
+    pub fn issue(&self) -> Option<DitherError<Infallible>> {
+        self.issue
+    }
+
+    // forgets all pending error, staying on the same raster
+    pub fn reset(&mut self) {
+        self.ring.reset(self.width + 1);
+    }
+
+    // starts over on a raster of the given width with its top left at origin
+    pub fn restart(&mut self, origin: Point, width: usize) {
+        assert!(width > 0 && width <= self.max_width());
+
+        self.ring.reset(width + 1);
+        self.origin = origin;
+        self.width = width;
+        self.position = 0;
+    }
+
+    // position of a row's first pixel on the raster of the given width, its first column at start.x,
+    // keeping the current raster (and its pending error) if start lies on it, starting a new one at start if not
+    fn enter(&mut self, start: Point, width: usize) -> usize {
+        if start.x != self.origin.x || start.y < self.origin.y || width != self.width {
+            self.restart(start, width);
+        }
+
+        (start.y - self.origin.y) as usize * width
+    }
+
+    // position of a row width long starting at start on the current raster, if all of it lies on it
+    fn locate(&self, start: Point, width: usize) -> Option<usize> {
+        let x = usize::try_from(start.x - self.origin.x).ok()?;
+        let y = usize::try_from(start.y - self.origin.y).ok()?;
+
+        (x + width <= self.width).then(|| y * self.width + x)
+    }
+
+    // position of an area's top left pixel on the raster it is dithered on, None if too wide for the ring
+    // a single row, as filled primitives are drawn a scanline at a time, stays on the current raster if it
+    // lies on it and goes on the display-wide one draw_iter uses if not, so error carries from row to row
+    // anything else is an image of its own, as entered above
+    fn enter_area(&mut self, area: &Rectangle) -> Option<usize> {
+        let (start, width) = (area.top_left, area.size.width as usize);
+        let max_width = self.max_width();
+
+        if area.size.height == 1 {
+            if self.locate(start, width).is_none()
+                && raster_position(start, max_width)
+                    .is_some_and(|_| start.x as usize + width <= max_width)
+            {
+                self.restart(Point::zero(), max_width);
+            }
+            if let Some(position) = self.locate(start, width) {
+                return Some(position);
+            }
+        }
+
+        (width <= max_width).then(|| self.enter(start, width))
+    }
+
+    // whether any error is still owed to pixels to come
+    fn pending(&self) -> bool {
+        (0..=self.width).any(|i| self.ring[i] != Accumulator::default())
+    }
+
+    // moves to position, any pending error is dropped if that means going back
+    pub fn seek(&mut self, position: usize) {
+        if position < self.position || position - self.position > self.width {
+            // everything pending has been skipped over
+            self.ring.reset(self.width + 1);
+        } else {
+            for _ in self.position..position {
+                self.ring.push(Accumulator::default());
+            }
+        }
+        self.position = position;
+    }
+
+    // quantizes the pixel at the current position and spreads its error onto the pixels after it
+    pub fn diffuse<C, D, F>(&mut self, color: C, closest_color_fn: &F) -> D
+    where
+        C: From<Accumulator> + Into<Accumulator>,
+        F: Fn(C) -> (D, QuantizationError),
+    {
+        const {
+            assert!(
+                kernel::reachable::<K>(),
+                "kernel reaches past the error buffer"
+            )
+        };
+
+        let width = self.width;
+        let x = self.position % width;
+
+        let (dithered_color, quantization_error) =
+            closest_color_fn(C::from(color.into() + self.ring[0]));
+
+        let mut below_right = Accumulator::default();
+        for (target, dy, share) in kernel::shares::<K>(x, width, quantization_error) {
+            match (dy, target > x) {
+                // a cell the ring hasn't got yet, it is pushed in below
+                (1, true) => below_right += share,
+                (1, _) => self.ring[width + target - x] += share,
+                _ => self.ring[target - x] += share,
+            }
+        }
+
+        self.ring.push(below_right);
+        self.position += 1;
+
+        dithered_color
+    }
+
+    // the DrawTarget methods of the dithering targets, which differ only in where the ring lives
+
+    // pixels are dithered at their own position, on a raster max_width wide from the display's origin
+    // anything in raster order works (a whole screen, a circle, a line of text), error only flows forward
+    // a draw may start anywhere, even behind the last one, and going back within it starts the diffusion
+    // over from there too, while pixels off the display are dropped as the display would
+    // none of that stops the draw, though what it had to work around is kept for issue()
+    pub fn draw_iter<Display, C, F, I>(
+        &mut self,
+        display: &mut Display,
+        closest_color_fn: &F,
+        pixels: I,
+    ) -> Result<(), DitherError<Display::Error>>
+    where
+        Display: DrawTarget,
+        C: PixelColor + From<Accumulator> + Into<Accumulator>,
+        F: Fn(C) -> (Display::Color, QuantizationError),
+        I: IntoIterator<Item = Pixel<C>>,
+    {
+        let width = self.max_width();
+        self.enter(Point::zero(), width);
+
+        let bounds = display.bounding_box();
+        let mut first = true;
+        self.issue = None;
+        display
+            .draw_iter(
+                pixels
+                    .into_iter()
+                    .filter(|Pixel(point, _)| bounds.contains(*point))
+                    .map(|Pixel(point, color)| {
+                        let Some(position) = raster_position(point, width) else {
+                            self.issue.get_or_insert(DitherError::OutOfBounds);
+                            return Pixel(point, closest_color_fn(color).0);
+                        };
+                        if position < self.position && !first {
+                            self.issue.get_or_insert(DitherError::OutOfOrder);
+                        }
+                        first = false;
+
+                        self.seek(position);
+                        Pixel(point, self.diffuse(color, closest_color_fn))
+                    }),
+            )
+            .map_err(DitherError::Display)
+    }
+
+    // dithered as an image of its own, error rows being as wide as the area, unless it carries on from
+    // where the last area of the same width and column left off, as bands do, or is a single row (see enter_area)
+    pub fn fill_contiguous<Display, C, F, I>(
+        &mut self,
+        display: &mut Display,
+        closest_color_fn: &F,
+        area: &Rectangle,
+        colors: I,
+    ) -> Result<(), DitherError<Display::Error>>
+    where
+        Display: DrawTarget,
+        C: PixelColor + From<Accumulator> + Into<Accumulator>,
+        F: Fn(C) -> (Display::Color, QuantizationError),
+        I: IntoIterator<Item = C>,
+    {
+        let width = area.size.width as usize;
+        if width == 0 || area.size.height == 0 {
+            return Ok(());
+        }
+        let Some(position) = self.enter_area(area) else {
+            return Err(DitherError::SizeMismatch);
+        };
+        self.seek(position);
+
+        display
+            .fill_contiguous(
+                area,
+                colors
+                    .into_iter()
+                    .take(width * area.size.height as usize)
+                    .map(|color| self.diffuse(color, closest_color_fn)),
+            )
+            .map_err(DitherError::Display)
+    }
+
+    pub fn fill_solid<Display, C, F>(
+        &mut self,
+        display: &mut Display,
+        closest_color_fn: &F,
+        area: &Rectangle,
+        color: C,
+    ) -> Result<(), DitherError<Display::Error>>
+    where
+        Display: DrawTarget,
+        C: PixelColor + From<Accumulator> + Into<Accumulator>,
+        F: Fn(C) -> (Display::Color, QuantizationError),
+    {
+        if area.is_zero_sized() {
+            return Ok(());
+        }
+
+        // a colour the display can show exactly has nothing to diffuse,
+        // and unless error is still owed where it goes there is nothing to pass on either
+        let (dithered_color, quantization_error) = closest_color_fn(color);
+        if quantization_error == QuantizationError::default() {
+            let owed = self.enter_area(area).is_some_and(|position| {
+                self.seek(position);
+                self.pending()
+            });
+            if !owed {
+                return display
+                    .fill_solid(area, dithered_color)
+                    .map_err(DitherError::Display);
+            }
+        }
+
+        self.fill_contiguous(display, closest_color_fn, area, core::iter::repeat(color))
+    }
+}
+
+// index of a point in a raster of the given width, if it lies on it
+fn raster_position(point: Point, width: usize) -> Option<usize> {
+    let x = usize::try_from(point.x).ok()?;
+    let y = usize::try_from(point.y).ok()?;
+
+    (x < width).then(|| y * width + x)
+}
@@ -0,0 +1,327 @@
+// colour distance metrics
+// every metric has the same shape, fn(Rgb888, Rgb888) -> u32, so they can be swapped freely
+// smaller is closer, but values are only comparable within a single metric
+// floating point metrics are returned as fixed point with 16 fractional bits
+use embedded_graphics_core::pixelcolor::{Rgb888, RgbColor};
+
+const FIXED_POINT_ONE: f32 = 65536.0;
+
+fn to_fixed(value: f32) -> u32 {
+    (value * FIXED_POINT_ONE) as u32
+}
+
+// sum of absolute channel differences, what the examples used to hand roll
+pub fn manhattan(a: Rgb888, b: Rgb888) -> u32 {
+    a.r().abs_diff(b.r()) as u32 + a.g().abs_diff(b.g()) as u32 + a.b().abs_diff(b.b()) as u32
+}
+
+// squared euclidean distance in gamma encoded rgb
+pub fn euclidean(a: Rgb888, b: Rgb888) -> u32 {
+    let dr = a.r().abs_diff(b.r()) as u32;
+    let dg = a.g().abs_diff(b.g()) as u32;
+    let db = a.b().abs_diff(b.b()) as u32;
+
+    dr * dr + dg * dg + db * db
+}
+
+// https://www.compuphase.com/cmetric.htm
+// squared, cheap and surprisingly good for its cost
+pub fn redmean(a: Rgb888, b: Rgb888) -> u32 {
+    let r_mean = (a.r() as u32 + b.r() as u32) / 2;
+    let dr = a.r().abs_diff(b.r()) as u32;
+    let dg = a.g().abs_diff(b.g()) as u32;
+    let db = a.b().abs_diff(b.b()) as u32;
+
+    (((512 + r_mean) * dr * dr) >> 8) + 4 * dg * dg + (((767 - r_mean) * db * db) >> 8)
+}
+
+// squared euclidean with channels weighted by their rec. 601 luma contribution
+pub fn weighted_luma(a: Rgb888, b: Rgb888) -> u32 {
+    let dr = a.r().abs_diff(b.r()) as u32;
+    let dg = a.g().abs_diff(b.g()) as u32;
+    let db = a.b().abs_diff(b.b()) as u32;
+
+    299 * dr * dr + 587 * dg * dg + 114 * db * db
+}
+
+// CIE 1976 ΔE*ab, plain euclidean distance in L*a*b*
+pub fn cie76(a: Rgb888, b: Rgb888) -> u32 {
+    to_fixed(delta_e76(Lab::from(a), Lab::from(b)))
+}
+
+// CIEDE2000 ΔE00
+pub fn ciede2000(a: Rgb888, b: Rgb888) -> u32 {
+    to_fixed(delta_e2000(Lab::from(a), Lab::from(b)))
+}
+
+// euclidean distance in OKLab
+pub fn oklab(a: Rgb888, b: Rgb888) -> u32 {
+    to_fixed(delta_oklab(OkLab::from(a), OkLab::from(b)))
+}
+
+// linear search of colour pairs
+// the rgb half of each pair is what is compared against
+pub fn nearest<C, F>(pairs: &[(C, Rgb888)], color: Rgb888, metric: F) -> Option<&(C, Rgb888)>
+where
+    F: Fn(Rgb888, Rgb888) -> u32,
+{
+    pairs.iter().min_by_key(|(_, rgb)| metric(color, *rgb))
+}
+
+fn srgb_to_linear(channel: u8) -> f32 {
+    let c = channel as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        libm::powf((c + 0.055) / 1.055, 2.4)
+    }
+}
+
+fn linear_rgb(color: Rgb888) -> (f32, f32, f32) {
+    (
+        srgb_to_linear(color.r()),
+        srgb_to_linear(color.g()),
+        srgb_to_linear(color.b()),
+    )
+}
+
+// CIE L*a*b* relative to D65
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Lab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+impl From<Rgb888> for Lab {
+    fn from(color: Rgb888) -> Self {
+        const X_N: f32 = 0.950_489;
+        const Z_N: f32 = 1.088_84;
+
+        fn f(t: f32) -> f32 {
+            const DELTA: f32 = 6.0 / 29.0;
+            if t > DELTA * DELTA * DELTA {
+                libm::cbrtf(t)
+            } else {
+                t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+            }
+        }
+
+        let (r, g, b) = linear_rgb(color);
+        let x = 0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b;
+        let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+        let z = 0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b;
+
+        let (fx, fy, fz) = (f(x / X_N), f(y), f(z / Z_N));
+
+        Self {
+            l: 116.0 * fy - 16.0,
+            a: 500.0 * (fx - fy),
+            b: 200.0 * (fy - fz),
+        }
+    }
+}
+
+pub fn delta_e76(x: Lab, y: Lab) -> f32 {
+    let dl = x.l - y.l;
+    let da = x.a - y.a;
+    let db = x.b - y.b;
+
+    libm::sqrtf(dl * dl + da * da + db * db)
+}
+
+// Sharma, Wu, Dalal (2005) with k_L = k_C = k_H = 1
+pub fn delta_e2000(x: Lab, y: Lab) -> f32 {
+    use core::f32::consts::PI;
+
+    fn hue(b: f32, a: f32) -> f32 {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            let h = libm::atan2f(b, a).to_degrees();
+            if h < 0.0 {
+                h + 360.0
+            } else {
+                h
+            }
+        }
+    }
+
+    let pow7 = |v: f32| libm::powf(v, 7.0);
+    let twenty_five_pow7 = 6_103_515_625.0f32;
+
+    let c1 = libm::sqrtf(x.a * x.a + x.b * x.b);
+    let c2 = libm::sqrtf(y.a * y.a + y.b * y.b);
+    let c_bar = (c1 + c2) / 2.0;
+    let g = 0.5 * (1.0 - libm::sqrtf(pow7(c_bar) / (pow7(c_bar) + twenty_five_pow7)));
+
+    let a1 = (1.0 + g) * x.a;
+    let a2 = (1.0 + g) * y.a;
+    let c1 = libm::sqrtf(a1 * a1 + x.b * x.b);
+    let c2 = libm::sqrtf(a2 * a2 + y.b * y.b);
+    let h1 = hue(x.b, a1);
+    let h2 = hue(y.b, a2);
+
+    let dl = y.l - x.l;
+    let dc = c2 - c1;
+    let dh = if c1 * c2 == 0.0 {
+        0.0
+    } else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    } else if h2 - h1 > 180.0 {
+        h2 - h1 - 360.0
+    } else {
+        h2 - h1 + 360.0
+    };
+    let dh = 2.0 * libm::sqrtf(c1 * c2) * libm::sinf((dh / 2.0).to_radians());
+
+    let l_bar = (x.l + y.l) / 2.0;
+    let c_bar = (c1 + c2) / 2.0;
+    let h_bar = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * libm::cosf((h_bar - 30.0).to_radians())
+        + 0.24 * libm::cosf((2.0 * h_bar).to_radians())
+        + 0.32 * libm::cosf((3.0 * h_bar + 6.0).to_radians())
+        - 0.20 * libm::cosf((4.0 * h_bar - 63.0).to_radians());
+    let d_theta = 30.0 * libm::expf(-((h_bar - 275.0) / 25.0) * ((h_bar - 275.0) / 25.0));
+    let r_c = 2.0 * libm::sqrtf(pow7(c_bar) / (pow7(c_bar) + twenty_five_pow7));
+    let s_l = 1.0
+        + (0.015 * (l_bar - 50.0) * (l_bar - 50.0))
+            / libm::sqrtf(20.0 + (l_bar - 50.0) * (l_bar - 50.0));
+    let s_c = 1.0 + 0.045 * c_bar;
+    let s_h = 1.0 + 0.015 * c_bar * t;
+    let r_t = -libm::sinf(2.0 * d_theta * PI / 180.0) * r_c;
+
+    let l_term = dl / s_l;
+    let c_term = dc / s_c;
+    let h_term = dh / s_h;
+
+    libm::sqrtf(l_term * l_term + c_term * c_term + h_term * h_term + r_t * c_term * h_term)
+}
+
+// https://bottosson.github.io/posts/oklab/
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OkLab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+impl From<Rgb888> for OkLab {
+    fn from(color: Rgb888) -> Self {
+        let (r, g, b) = linear_rgb(color);
+
+        let l = libm::cbrtf(0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b);
+        let m = libm::cbrtf(0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b);
+        let s = libm::cbrtf(0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b);
+
+        Self {
+            l: 0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+            a: 1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+            b: 0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+        }
+    }
+}
+
+pub fn delta_oklab(x: OkLab, y: OkLab) -> f32 {
+    let dl = x.l - y.l;
+    let da = x.a - y.a;
+    let db = x.b - y.b;
+
+    libm::sqrtf(dl * dl + da * da + db * db)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BLACK: Rgb888 = Rgb888::new(0x00, 0x00, 0x00);
+    const WHITE: Rgb888 = Rgb888::new(0xff, 0xff, 0xff);
+
+    #[test]
+    fn test_metrics() {
+        let orange = Rgb888::new(0xff, 0x80, 0x00);
+        let gray = Rgb888::new(0x80, 0x80, 0x80);
+        for metric in [
+            manhattan,
+            euclidean,
+            redmean,
+            weighted_luma,
+            cie76,
+            ciede2000,
+            oklab,
+        ] {
+            assert_eq!(metric(orange, orange), 0);
+            assert_eq!(metric(BLACK, WHITE), metric(WHITE, BLACK));
+            assert!(metric(BLACK, WHITE) > metric(BLACK, gray));
+        }
+    }
+
+    #[test]
+    fn test_lab() {
+        let white = Lab::from(WHITE);
+        assert!((white.l - 100.0).abs() < 0.01);
+        assert!(white.a.abs() < 0.01);
+        assert!(white.b.abs() < 0.01);
+
+        let white = OkLab::from(WHITE);
+        assert!((white.l - 1.0).abs() < 0.001);
+        assert!(white.a.abs() < 0.001);
+        assert!(white.b.abs() < 0.001);
+    }
+
+    // pairs from Sharma, Wu, Dalal's CIEDE2000 test data
+    #[test]
+    fn test_delta_e2000() {
+        for (x, y, expected) in [
+            ((50.0, 2.6772, -79.7751), (50.0, 0.0, -82.7485), 2.0425),
+            ((50.0, 2.5, 0.0), (73.0, 25.0, -18.0), 27.1492),
+            ((50.0, 2.5, 0.0), (50.0, 3.2592, 0.3350), 1.0000),
+            (
+                (2.0776, 0.0795, -1.1350),
+                (0.9033, -0.0636, -0.5514),
+                0.9082,
+            ),
+        ] {
+            let x = Lab {
+                l: x.0,
+                a: x.1,
+                b: x.2,
+            };
+            let y = Lab {
+                l: y.0,
+                a: y.1,
+                b: y.2,
+            };
+            assert!((delta_e2000(x, y) - expected).abs() < 0.001);
+            assert!((delta_e2000(y, x) - expected).abs() < 0.001);
+        }
+    }
+
+    #[test]
+    fn test_nearest() {
+        let pairs = [('k', BLACK), ('w', WHITE), ('r', Rgb888::new(0xff, 0, 0))];
+
+        assert_eq!(
+            nearest(&pairs, Rgb888::new(0x10, 0x10, 0x10), redmean)
+                .unwrap()
+                .0,
+            'k'
+        );
+        assert_eq!(
+            nearest(&pairs, Rgb888::new(0xe0, 0x20, 0x20), ciede2000)
+                .unwrap()
+                .0,
+            'r'
+        );
+        assert!(nearest::<char, _>(&[], WHITE, oklab).is_none());
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{
+    diffusion::Diffusion, kernel::Kernel, wrapping_vec::WrappingSlice, DitherError,
+    QuantizationError,
+};
+use core::{convert::Infallible, marker::PhantomData};
+use embedded_graphics_core::geometry::Point;
+
+// dithers a stream of colours taken to be rows width long, lazily, a colour out for each colour in
+// the kernel is passed by value (they're all unit structs) so nothing needs spelling out
+// buffer holds the error still to be diffused and needs at least width + 1 cells
+//
+//     let mut buffer = [QuantizationError::default(); 65];
+//     let dithered = pixels.dither(FloydSteinberg, 64, &closest_color_fn, &mut buffer)?;
+pub trait Dither: Iterator + Sized
+where
+    Self::Item: From<QuantizationError> + Into<QuantizationError>,
+{
+    fn dither<'a, K, D, F>(
+        self,
+        _kernel: K,
+        width: usize,
+        closest_color_fn: &'a F,
+        buffer: &'a mut [QuantizationError],
+    ) -> Result<Dithered<'a, Self, K, D, F>, DitherError<Infallible>>
+    where
+        K: Kernel,
+        F: Fn(Self::Item) -> (D, QuantizationError),
+    {
+        Dithered::new(self, width, closest_color_fn, buffer)
+    }
+}
+
+impl<I> Dither for I
+where
+    I: Iterator,
+    I::Item: From<QuantizationError> + Into<QuantizationError>,
+{
+}
+
+pub struct Dithered<'a, I, K, D, F>
+where
+    I: Iterator,
+    I::Item: From<QuantizationError> + Into<QuantizationError>,
+    K: Kernel,
+    F: Fn(I::Item) -> (D, QuantizationError),
+{
+    pixels: I,
+    closest_color_fn: &'a F,
+    diffusion: Diffusion<WrappingSlice<'a, QuantizationError>, K>,
+    phantom: PhantomData<D>,
+}
+
+impl<'a, I, K, D, F> Dithered<'a, I, K, D, F>
+where
+    I: Iterator,
+    I::Item: From<QuantizationError> + Into<QuantizationError>,
+    K: Kernel,
+    F: Fn(I::Item) -> (D, QuantizationError),
+{
+    pub fn new(
+        pixels: I,
+        width: usize,
+        closest_color_fn: &'a F,
+        buffer: &'a mut [QuantizationError],
+    ) -> Result<Self, DitherError<Infallible>> {
+        if width == 0 || buffer.len() <= width {
+            return Err(DitherError::SizeMismatch);
+        }
+
+        let mut diffusion = Diffusion::new(WrappingSlice::new(buffer));
+        diffusion.restart(Point::zero(), width);
+
+        Ok(Self {
+            pixels,
+            closest_color_fn,
+            diffusion,
+            phantom: PhantomData,
+        })
+    }
+}
+
+impl<I, K, D, F> Iterator for Dithered<'_, I, K, D, F>
+where
+    I: Iterator,
+    I::Item: From<QuantizationError> + Into<QuantizationError>,
+    K: Kernel,
+    F: Fn(I::Item) -> (D, QuantizationError),
+{
+    type Item = D;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let color = self.pixels.next()?;
+
+        Some(self.diffusion.diffuse(color, self.closest_color_fn))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.pixels.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        kernel::{FalseFloydSteinberg, FloydSteinberg, Threshold},
+        raw,
+        test_util::{BLACK_WHITE_INDEXED, GREY},
+    };
+    use embedded_graphics_core::pixelcolor::Rgb888;
+
+    #[test]
+    fn test_kernels() {
+        let closest = |color| BLACK_WHITE_INDEXED.with_error(color);
+        let mut buffer = [QuantizationError::default(); 4];
+        let mut out = [0; 9];
+
+        // the same as everything else for floyd-steinberg
+        let mut expected = [0; 9];
+        raw::dither(&[GREY; 9], 3, &closest, &mut expected, &mut buffer).unwrap();
+        for (out, pixel) in out.iter_mut().zip(
+            [GREY; 9]
+                .into_iter()
+                .dither(FloydSteinberg, 3, &closest, &mut buffer)
+                .unwrap(),
+        ) {
+            *out = pixel;
+        }
+        assert_eq!(out, expected);
+
+        // 128 to white leaves -127, -48 of it to the right and below, -32 below right
+        let dithered = [GREY; 4]
+            .into_iter()
+            .dither(FalseFloydSteinberg, 2, &closest, &mut buffer)
+            .unwrap();
+        for (out, pixel) in out.iter_mut().zip(dithered) {
+            *out = pixel;
+        }
+        assert_eq!(out[..4], [1, 0, 0, 1]);
+
+        let dithered = [GREY; 4]
+            .into_iter()
+            .dither(Threshold, 2, &closest, &mut buffer)
+            .unwrap();
+        assert!(dithered.eq([1; 4]));
+    }
+
+    #[test]
+    fn test_lazy() {
+        let closest = |color| BLACK_WHITE_INDEXED.with_error(color);
+        let mut buffer = [QuantizationError::default(); 65];
+
+        // an endless stream, taking only what is needed
+        let mut dithered = core::iter::repeat(GREY)
+            .dither(FloydSteinberg, 64, &closest, &mut buffer)
+            .unwrap();
+        assert_eq!(dithered.next(), Some(1));
+        assert_eq!(dithered.next(), Some(0));
+        assert_eq!(dithered.size_hint(), (usize::MAX, None));
+
+        assert!(matches!(
+            core::iter::empty::<Rgb888>().dither(FloydSteinberg, 65, &closest, &mut buffer),
+            Err(DitherError::SizeMismatch)
+        ));
+    }
+}
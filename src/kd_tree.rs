@@ -0,0 +1,214 @@
+use crate::QuantizationError;
+use embedded_graphics_core::pixelcolor::{Rgb888, RgbColor};
+
+// nearest colour search for big palettes without a linear scan per pixel
+// an implicit k-d tree: entries are reordered so each range's median splits it along one axis,
+// that median sitting in the middle of the range, so no node storage is needed beyond the axis
+//
+// distance is (weighted) squared euclidean in rgb, which is what lets whole subtrees be pruned
+// weights of (1, 1, 1) match distance::euclidean, (299, 587, 114) match distance::weighted_luma
+#[derive(Debug, Clone)]
+pub struct KdTree<C, const P: usize>
+where
+    C: Copy,
+{
+    entries: [(C, Rgb888); P],
+    axes: [u8; P],
+    weights: [u32; 3],
+}
+
+fn channel(color: Rgb888, axis: u8) -> u8 {
+    match axis {
+        0 => color.r(),
+        1 => color.g(),
+        _ => color.b(),
+    }
+}
+
+impl<C, const P: usize> KdTree<C, P>
+where
+    C: Copy,
+{
+    pub fn new(entries: [(C, Rgb888); P]) -> Self {
+        Self::with_weights(entries, (1, 1, 1))
+    }
+
+    pub fn with_weights(entries: [(C, Rgb888); P], weights: (u32, u32, u32)) -> Self {
+        const { assert!(P > 0, "a palette needs at least one colour") };
+
+        let mut result = Self {
+            entries,
+            axes: [0; P],
+            weights: [weights.0, weights.1, weights.2],
+        };
+        result.build(0, P);
+
+        result
+    }
+
+    // splits each range along whichever axis its colours are most spread out on
+    fn build(&mut self, lo: usize, hi: usize) {
+        if lo >= hi {
+            return;
+        }
+
+        let range = &mut self.entries[lo..hi];
+        let axis = (0..3u8)
+            .max_by_key(|axis| {
+                let values = range.iter().map(|(_, rgb)| channel(*rgb, *axis));
+                let spread = values.clone().max().unwrap() - values.min().unwrap();
+                spread as u32 * self.weights[*axis as usize]
+            })
+            .unwrap();
+        range.sort_unstable_by_key(|(_, rgb)| channel(*rgb, axis));
+
+        let mid = lo + (hi - lo) / 2;
+        self.axes[mid] = axis;
+        self.build(lo, mid);
+        self.build(mid + 1, hi);
+    }
+
+    fn distance(&self, a: Rgb888, b: Rgb888) -> u32 {
+        (0..3)
+            .map(|axis| {
+                let d = channel(a, axis).abs_diff(channel(b, axis)) as u32;
+                self.weights[axis as usize] * d * d
+            })
+            .sum()
+    }
+
+    fn search(&self, lo: usize, hi: usize, color: Rgb888, best: &mut (usize, u32)) {
+        if lo >= hi {
+            return;
+        }
+
+        let mid = lo + (hi - lo) / 2;
+        let (_, rgb) = self.entries[mid];
+        let distance = self.distance(color, rgb);
+        if distance < best.1 {
+            *best = (mid, distance);
+        }
+
+        let axis = self.axes[mid];
+        let along = channel(color, axis) as i32 - channel(rgb, axis) as i32;
+        let (near, far) = if along < 0 {
+            ((lo, mid), (mid + 1, hi))
+        } else {
+            ((mid + 1, hi), (lo, mid))
+        };
+
+        self.search(near.0, near.1, color, best);
+        // the far side can only be closer if the splitting plane is
+        if self.weights[axis as usize] * ((along * along) as u32) < best.1 {
+            self.search(far.0, far.1, color, best);
+        }
+    }
+
+    pub fn nearest<I>(&self, color: I) -> (C, Rgb888)
+    where
+        I: Into<Rgb888>,
+    {
+        let mut best = (0, u32::MAX);
+        self.search(0, P, color.into(), &mut best);
+
+        self.entries[best.0]
+    }
+
+    // suitable for use as DitherTarget's closest_color_fn, as with Palette::with_error
+    pub fn with_error<I>(&self, color: I) -> (C, QuantizationError)
+    where
+        I: Into<Rgb888>,
+    {
+        let color = color.into();
+        let (closest, rgb) = self.nearest(color);
+
+        (
+            closest,
+            QuantizationError::from(color) - QuantizationError::from(rgb),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{distance, palette::Palette};
+
+    // the 256 colour xterm palette
+    fn xterm() -> [(u8, Rgb888); 256] {
+        const BASIC: [(u8, u8, u8); 16] = [
+            (0x00, 0x00, 0x00),
+            (0x80, 0x00, 0x00),
+            (0x00, 0x80, 0x00),
+            (0x80, 0x80, 0x00),
+            (0x00, 0x00, 0x80),
+            (0x80, 0x00, 0x80),
+            (0x00, 0x80, 0x80),
+            (0xc0, 0xc0, 0xc0),
+            (0x80, 0x80, 0x80),
+            (0xff, 0x00, 0x00),
+            (0x00, 0xff, 0x00),
+            (0xff, 0xff, 0x00),
+            (0x00, 0x00, 0xff),
+            (0xff, 0x00, 0xff),
+            (0x00, 0xff, 0xff),
+            (0xff, 0xff, 0xff),
+        ];
+        let level = |i: usize| if i == 0 { 0 } else { (55 + 40 * i) as u8 };
+
+        let mut entries = [(0, Rgb888::default()); 256];
+        for (i, entry) in entries.iter_mut().enumerate() {
+            let rgb = match i {
+                0..=15 => Rgb888::new(BASIC[i].0, BASIC[i].1, BASIC[i].2),
+                16..=231 => {
+                    let i = i - 16;
+                    Rgb888::new(level(i / 36), level((i / 6) % 6), level(i % 6))
+                }
+                _ => {
+                    let v = (8 + 10 * (i - 232)) as u8;
+                    Rgb888::new(v, v, v)
+                }
+            };
+            *entry = (i as u8, rgb);
+        }
+        entries
+    }
+
+    #[test]
+    fn test_matches_linear_search() {
+        for (weights, metric) in [
+            ((1, 1, 1), distance::euclidean as fn(Rgb888, Rgb888) -> u32),
+            ((299, 587, 114), distance::weighted_luma),
+        ] {
+            let tree = KdTree::with_weights(xterm(), weights);
+            let palette = Palette::new(xterm(), metric);
+
+            for r in (0..=255).step_by(7) {
+                for g in (0..=255).step_by(11) {
+                    for b in (0..=255).step_by(13) {
+                        let color = Rgb888::new(r, g, b);
+                        // ties can go either way, so compare distances not entries
+                        assert_eq!(
+                            metric(color, tree.nearest(color).1),
+                            metric(color, palette.nearest(color).1)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_error() {
+        let tree = KdTree::new(xterm());
+
+        // pure red is both 9 and 196
+        let (index, error) = tree.with_error(Rgb888::new(0xff, 0x00, 0x01));
+        assert!(index == 9 || index == 196);
+        assert_eq!(error, QuantizationError::new((0, 0, 1)));
+        assert_eq!(
+            tree.nearest(Rgb888::new(0x09, 0x09, 0x09)).1,
+            Rgb888::new(8, 8, 8)
+        );
+    }
+}
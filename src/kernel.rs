@@ -0,0 +1,74 @@
+use crate::QuantizationError;
+
+// how a pixel's quantization error is shared out among the pixels after it
+// each weight is (dx, dy, sixteenths), dy being 0 (same row, dx > 0) or 1 (next row, dx at most 1),
+// which is as far as the one row error buffer reaches
+// shares that would fall off the left or right of a row are dropped
+pub trait Kernel {
+    const WEIGHTS: &'static [(i8, u8, i16)];
+}
+
+// 7/16 right, 3/16 below left, 5/16 below, 1/16 below right
+pub struct FloydSteinberg;
+
+impl Kernel for FloydSteinberg {
+    const WEIGHTS: &'static [(i8, u8, i16)] = &[(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)];
+}
+
+// the cheap three way split, 3/8 right, 3/8 below, 2/8 below right
+pub struct FalseFloydSteinberg;
+
+impl Kernel for FalseFloydSteinberg {
+    const WEIGHTS: &'static [(i8, u8, i16)] = &[(1, 0, 6), (0, 1, 6), (1, 1, 4)];
+}
+
+// no diffusion at all, just the nearest colour
+pub struct Threshold;
+
+impl Kernel for Threshold {
+    const WEIGHTS: &'static [(i8, u8, i16)] = &[];
+}
+
+// whether every weight lands somewhere the error buffer can hold
+pub(crate) const fn reachable<K: Kernel>() -> bool {
+    let mut i = 0;
+    while i < K::WEIGHTS.len() {
+        let (dx, dy, _) = K::WEIGHTS[i];
+        if !((dy == 0 && dx > 0) || (dy == 1 && dx <= 1)) {
+            return false;
+        }
+        i += 1;
+    }
+
+    true
+}
+
+// whether no weight goes more than one pixel right either, as a tile's edge buffers only hold the
+// column just right of it
+pub(crate) const fn adjacent<K: Kernel>() -> bool {
+    let mut i = 0;
+    while i < K::WEIGHTS.len() {
+        if K::WEIGHTS[i].0 > 1 {
+            return false;
+        }
+        i += 1;
+    }
+
+    reachable::<K>()
+}
+
+// where K sends the error of a pixel in column x of a row width wide, as (column, rows down, share)
+// shares falling off either edge of the row are left out
+pub(crate) fn shares<K: Kernel>(
+    x: usize,
+    width: usize,
+    quantization_error: QuantizationError,
+) -> impl Iterator<Item = (usize, usize, QuantizationError)> {
+    K::WEIGHTS.iter().filter_map(move |&(dx, dy, weight)| {
+        let to_x = x
+            .checked_add_signed(dx as isize)
+            .filter(|to_x| *to_x < width)?;
+
+        Some((to_x, dy as usize, (quantization_error * weight) >> 4))
+    })
+}
@@ -0,0 +1,13 @@
+// shared by the 3D tables (colour cubes, luts): n samples per axis spread evenly over 0..=255
+
+// value of the i'th of n samples
+pub(crate) const fn sample(i: usize, n: usize) -> u8 {
+    ((i * 255 + (n - 1) / 2) / (n - 1)) as u8
+}
+
+// index of whichever of n samples is closest to value
+// inverse of sample, so a cell is looked up by the same value it was computed from
+#[cfg_attr(not(feature = "color_cube"), allow(dead_code))]
+pub(crate) const fn cell(value: u8, n: usize) -> usize {
+    (value as usize * (n - 1) + 127) / 255
+}
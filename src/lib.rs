@@ -1,27 +1,61 @@
 #![no_std]
 
+#[cfg(feature = "std")]
+extern crate std;
+
 mod accumulator;
+mod diffusion;
+#[cfg(any(feature = "color_cube", feature = "lut"))]
+mod lattice;
+#[cfg(test)]
+mod test_util;
 mod wrapping_vec;
 
+pub mod buffered;
+pub mod distance;
+pub mod iter;
+pub mod kd_tree;
+pub mod kernel;
+pub mod palette;
+pub mod raw;
+pub mod row;
+pub mod tile;
+
 #[cfg(feature = "cga")]
 pub mod cga;
 #[cfg(feature = "color_cube")]
 pub mod color_cube;
+#[cfg(feature = "lut")]
+pub mod lut;
 #[cfg(feature = "terminal")]
 pub mod terminal;
 
 use accumulator::Accumulator;
+use diffusion::Diffusion;
+use wrapping_vec::{WrappingSlice, WrappingVec};
 pub type QuantizationError = Accumulator;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DitherError<E> {
+    // from the display being drawn to
+    Display(E),
+    // an area wider than the error buffer, or a buffer too small to dither anything
+    SizeMismatch,
+    // something past what the error buffer covers, a pixel right of its rows (see DitherTarget::issue) say
+    OutOfBounds,
+    // a pixel at or before one already drawn in the same draw (see DitherTarget::issue)
+    OutOfOrder,
+}
+
 use embedded_graphics_core::{
     draw_target::DrawTarget,
-    geometry::{OriginDimensions, Point, Size},
+    geometry::{OriginDimensions, Size},
     pixelcolor::{PixelColor, RgbColor},
     primitives::Rectangle,
     Pixel,
 };
 
-use core::marker::PhantomData;
+use core::{convert::Infallible, marker::PhantomData};
 
 pub struct DitherTarget<'a, Display, C, F, const WIDTH: usize>
 where
@@ -31,7 +65,7 @@ where
 {
     display: &'a mut Display,
     closest_color_fn: &'a F,
-    accumulation_buffer: crate::wrapping_vec::WrappingVec<Accumulator, WIDTH>,
+    diffusion: Diffusion<WrappingVec<Accumulator, WIDTH>>,
     phantom: PhantomData<C>,
 }
 
@@ -42,21 +76,34 @@ where
     C: PixelColor + From<Accumulator> + Into<Accumulator> + RgbColor,
 {
     pub fn new(display: &'a mut Display, closest_color_fn: &'a F) -> Self {
+        const {
+            assert!(
+                WIDTH > 0,
+                "the error buffer needs to be at least one pixel wide"
+            )
+        };
+
         Self {
             display,
             closest_color_fn,
-            accumulation_buffer: crate::wrapping_vec::WrappingVec::new(&mut core::iter::repeat(
+            diffusion: Diffusion::new(WrappingVec::new(&mut core::iter::repeat(
                 Accumulator::default(),
-            )),
+            ))),
             phantom: PhantomData,
         }
     }
 
-    fn initialize_accumulation_buffer<I>(&mut self, pixels: &mut I)
-    where
-        I: Iterator<Item = Accumulator>,
-    {
-        self.accumulation_buffer = crate::wrapping_vec::WrappingVec::new(pixels);
+    // error still owed by what was drawn last carries on into the next draw when that continues the same
+    // raster (the next band of an image, say), this forgets it
+    pub fn reset(&mut self) {
+        self.diffusion.reset();
+    }
+
+    // the first thing the last draw_iter had to work around, none of which stops it drawing every pixel:
+    // OutOfBounds for pixels right of the error buffer's rows, which are drawn undithered,
+    // OutOfOrder for a pixel at or before the one before it, the diffusion starting over from there
+    pub fn issue(&self) -> Option<DitherError<Infallible>> {
+        self.diffusion.issue()
     }
 }
 
@@ -67,41 +114,128 @@ where
     C: PixelColor + From<Accumulator> + RgbColor,
 {
     type Color = C;
-    type Error = Display::Error;
+    type Error = DitherError<Display::Error>;
 
     fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
     where
         I: IntoIterator<Item = Pixel<Self::Color>>,
     {
-        let mut pixels = pixels.into_iter().map(|pixel| pixel.1.into());
+        self.diffusion
+            .draw_iter(self.display, self.closest_color_fn, pixels)
+    }
 
-        self.initialize_accumulation_buffer(&mut pixels);
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        self.diffusion
+            .fill_contiguous(self.display, self.closest_color_fn, area, colors)
+    }
 
-        self.display.fill_contiguous(
-            &Rectangle::new(Point::zero(), self.size()),
-            pixels.map(|horizon_pixel| {
-                let (dithered_color, quantization_error): (Display::Color, QuantizationError) =
-                    (self.closest_color_fn)(self.accumulation_buffer[0].into());
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.diffusion
+            .fill_solid(self.display, self.closest_color_fn, area, color)
+    }
+}
 
-                // assert!(
-                //     (self.closest_color_fn)(closest_color_rgb) == dithered_color
-                // );
+impl<'a, Display, C, F, const WIDTH: usize> OriginDimensions
+    for DitherTarget<'a, Display, C, F, WIDTH>
+where
+    F: Fn(C) -> (Display::Color, QuantizationError),
+    Display: DrawTarget + OriginDimensions,
+    C: PixelColor + From<Accumulator> + RgbColor,
+{
+    fn size(&self) -> Size {
+        self.display.size()
+    }
+}
 
-                self.accumulation_buffer[1] += (quantization_error * 7) >> 4;
-                self.accumulation_buffer[WIDTH - 1] += (quantization_error * 3) >> 4;
-                self.accumulation_buffer[WIDTH] += (quantization_error * 5) >> 4;
-                self.accumulation_buffer[WIDTH + 1] += (quantization_error) >> 4;
+// same as DitherTarget but with the error buffer borrowed from the caller and sized at runtime
+// so one target can dither images of any width up to buffer.len() - 1
+// raw pixels (draw_iter) are taken to be on rows that wide too
+pub struct SliceDitherTarget<'a, Display, C, F>
+where
+    F: Fn(C) -> (Display::Color, QuantizationError),
+    Display: DrawTarget + OriginDimensions,
+    C: PixelColor + From<Accumulator> + RgbColor,
+{
+    display: &'a mut Display,
+    closest_color_fn: &'a F,
+    diffusion: Diffusion<WrappingSlice<'a, Accumulator>>,
+    phantom: PhantomData<C>,
+}
 
-                self.accumulation_buffer.push(horizon_pixel);
+impl<'a, Display, C, F> SliceDitherTarget<'a, Display, C, F>
+where
+    F: Fn(C) -> (Display::Color, QuantizationError),
+    Display: DrawTarget + OriginDimensions,
+    C: PixelColor + From<Accumulator> + Into<Accumulator> + RgbColor,
+{
+    // buffer needs at least two cells, for images one pixel wide
+    pub fn new(
+        display: &'a mut Display,
+        closest_color_fn: &'a F,
+        buffer: &'a mut [QuantizationError],
+    ) -> Result<Self, DitherError<Display::Error>> {
+        if buffer.len() < 2 {
+            return Err(DitherError::SizeMismatch);
+        }
+
+        Ok(Self {
+            display,
+            closest_color_fn,
+            diffusion: Diffusion::new(WrappingSlice::new(buffer)),
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn max_width(&self) -> usize {
+        self.diffusion.max_width()
+    }
+
+    // as DitherTarget::reset
+    pub fn reset(&mut self) {
+        self.diffusion.reset();
+    }
 
-                dithered_color
-            }),
-        )
+    // as DitherTarget::issue
+    pub fn issue(&self) -> Option<DitherError<Infallible>> {
+        self.diffusion.issue()
     }
 }
 
-impl<'a, Display, C, F, const WIDTH: usize> OriginDimensions
-    for DitherTarget<'a, Display, C, F, WIDTH>
+impl<'a, Display, C, F> DrawTarget for SliceDitherTarget<'a, Display, C, F>
+where
+    F: Fn(C) -> (Display::Color, QuantizationError),
+    Display: DrawTarget + OriginDimensions,
+    C: PixelColor + From<Accumulator> + RgbColor,
+{
+    type Color = C;
+    type Error = DitherError<Display::Error>;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        self.diffusion
+            .draw_iter(self.display, self.closest_color_fn, pixels)
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        self.diffusion
+            .fill_contiguous(self.display, self.closest_color_fn, area, colors)
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.diffusion
+            .fill_solid(self.display, self.closest_color_fn, area, color)
+    }
+}
+
+impl<'a, Display, C, F> OriginDimensions for SliceDitherTarget<'a, Display, C, F>
 where
     F: Fn(C) -> (Display::Color, QuantizationError),
     Display: DrawTarget + OriginDimensions,
@@ -111,3 +245,485 @@ where
         self.display.size()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{BLACK_WHITE, GREY};
+    use embedded_graphics::{
+        mock_display::MockDisplay,
+        pixelcolor::Rgb888,
+        prelude::*,
+        primitives::{Circle, PrimitiveStyle, Rectangle},
+    };
+
+    #[test]
+    fn test_primitive() {
+        let mut display = MockDisplay::<Rgb888>::new();
+        let closest = |color| BLACK_WHITE.with_error(color);
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut display, &closest);
+
+        let circle = Circle::new(Point::new(20, 10), 21);
+        circle
+            .into_styled(PrimitiveStyle::with_fill(GREY))
+            .draw(&mut target)
+            .unwrap();
+
+        // exactly the circle is drawn, about half of it white
+        let mut white = 0;
+        for point in display.bounding_box().points() {
+            let drawn = display.get_pixel(point);
+            assert_eq!(drawn.is_some(), circle.contains(point));
+            if drawn == Some(Rgb888::WHITE) {
+                white += 1;
+            }
+        }
+        let total = circle.points().count();
+        assert!(white * 10 > total * 4 && white * 10 < total * 6);
+    }
+
+    #[test]
+    fn test_exact_colors() {
+        let mut display = MockDisplay::<Rgb888>::new();
+        let closest = |color| BLACK_WHITE.with_error(color);
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut display, &closest);
+
+        Rectangle::new(Point::new(3, 5), Size::new(10, 4))
+            .into_styled(PrimitiveStyle::with_fill(Rgb888::WHITE))
+            .draw(&mut target)
+            .unwrap();
+        Rectangle::new(Point::new(30, 40), Size::new(7, 7))
+            .into_styled(PrimitiveStyle::with_fill(Rgb888::BLACK))
+            .draw(&mut target)
+            .unwrap();
+
+        for point in display.bounding_box().points() {
+            let expected = if Rectangle::new(Point::new(3, 5), Size::new(10, 4)).contains(point) {
+                Some(Rgb888::WHITE)
+            } else if Rectangle::new(Point::new(30, 40), Size::new(7, 7)).contains(point) {
+                Some(Rgb888::BLACK)
+            } else {
+                None
+            };
+            assert_eq!(display.get_pixel(point), expected);
+        }
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let mut display = MockDisplay::<Rgb888>::new();
+        display.set_allow_overdraw(true);
+        let closest = |color| BLACK_WHITE.with_error(color);
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut display, &closest);
+
+        // bottom row first, then the top one, going back starts over without stopping the draw
+        target
+            .draw_iter([
+                Pixel(Point::new(0, 1), GREY),
+                Pixel(Point::new(1, 1), GREY),
+                Pixel(Point::new(0, 0), GREY),
+                Pixel(Point::new(1, 0), GREY),
+            ])
+            .unwrap();
+        assert_eq!(target.issue(), Some(DitherError::OutOfOrder));
+        // starting behind the last draw is fine though
+        target
+            .draw_iter([Pixel(Point::new(0, 0), GREY), Pixel(Point::new(1, 0), GREY)])
+            .unwrap();
+        assert_eq!(target.issue(), None);
+
+        display.assert_pattern(&["WK", "WK"]);
+    }
+
+    #[test]
+    fn test_clipped() {
+        use embedded_graphics::primitives::Line;
+
+        let mut display = MockDisplay::<Rgb888>::new();
+        let closest = |color| BLACK_WHITE.with_error(color);
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut display, &closest);
+
+        // partly off the display, what is on it is still drawn
+        Line::new(Point::new(-10, 5), Point::new(30, 5))
+            .into_styled(PrimitiveStyle::with_stroke(GREY, 1))
+            .draw(&mut target)
+            .unwrap();
+        assert_eq!(target.issue(), None);
+        // going up, each pixel is on a row above the last and starts over
+        Line::new(Point::new(0, 30), Point::new(20, 10))
+            .into_styled(PrimitiveStyle::with_stroke(GREY, 1))
+            .draw(&mut target)
+            .unwrap();
+        assert_eq!(target.issue(), Some(DitherError::OutOfOrder));
+
+        let drawn = display
+            .bounding_box()
+            .points()
+            .filter(|point| display.get_pixel(*point).is_some())
+            .count();
+        assert_eq!(drawn, 31 + 21);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut display = MockDisplay::<Rgb888>::new();
+        let closest = |color| BLACK_WHITE.with_error(color);
+        let mut target: DitherTarget<'_, _, Rgb888, _, 4> =
+            DitherTarget::new(&mut display, &closest);
+
+        // off the display is dropped as usual, right of the error buffer drawn undithered
+        target.draw_iter([Pixel(Point::new(-1, 0), GREY)]).unwrap();
+        assert_eq!(target.issue(), None);
+        target
+            .draw_iter([Pixel(Point::new(0, 0), GREY), Pixel(Point::new(4, 0), GREY)])
+            .unwrap();
+        assert_eq!(target.issue(), Some(DitherError::OutOfBounds));
+        assert_eq!(
+            target.fill_solid(&Rectangle::new(Point::new(0, 1), Size::new(5, 1)), GREY),
+            Err(DitherError::SizeMismatch)
+        );
+        // exact colours need no buffer, so any width will do
+        target
+            .fill_solid(
+                &Rectangle::new(Point::new(0, 2), Size::new(5, 1)),
+                Rgb888::WHITE,
+            )
+            .unwrap();
+        display.assert_pattern(&["W   W", "     ", "WWWWW"]);
+
+        let mut display = MockDisplay::<Rgb888>::new();
+        let mut buffer = [QuantizationError::default(); 1];
+        assert!(matches!(
+            SliceDitherTarget::new(&mut display, &closest, &mut buffer),
+            Err(DitherError::SizeMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_fill_solid() {
+        let mut display = MockDisplay::<Rgb888>::new();
+        let closest = |color| BLACK_WHITE.with_error(color);
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut display, &closest);
+
+        let area = Rectangle::new(Point::new(10, 20), Size::new(20, 10));
+        target.fill_solid(&area, GREY).unwrap();
+
+        let mut white = 0;
+        for point in display.bounding_box().points() {
+            let drawn = display.get_pixel(point);
+            assert_eq!(drawn.is_some(), area.contains(point));
+            if drawn == Some(Rgb888::WHITE) {
+                white += 1;
+            }
+        }
+        assert!((90..=110).contains(&white));
+
+        // the top left pixel starts the pattern, whatever the area's offset
+        assert_eq!(display.get_pixel(Point::new(10, 20)), Some(Rgb888::WHITE));
+        assert_eq!(display.get_pixel(Point::new(11, 20)), Some(Rgb888::BLACK));
+
+        // the error buffer only needs to be as wide as the area, wherever it is
+        let mut display = MockDisplay::<Rgb888>::new();
+        let mut target: DitherTarget<'_, _, Rgb888, _, 16> =
+            DitherTarget::new(&mut display, &closest);
+        let area = Rectangle::new(Point::new(30, 20), Size::new(8, 10));
+        target.fill_solid(&area, GREY).unwrap();
+
+        let white = area
+            .points()
+            .filter(|point| display.get_pixel(*point) == Some(Rgb888::WHITE))
+            .count();
+        assert!((35..=45).contains(&white));
+        assert_eq!(display.get_pixel(Point::new(30, 20)), Some(Rgb888::WHITE));
+    }
+
+    #[test]
+    fn test_image() {
+        use embedded_graphics::image::{Image, ImageRaw};
+
+        // a 2x2 grey image at an offset, then the same one at the origin
+        const DATA: [u8; 12] = [0x80; 12];
+        let raw = ImageRaw::<Rgb888>::new(&DATA, 2);
+
+        let mut display = MockDisplay::<Rgb888>::new();
+        let closest = |color| BLACK_WHITE.with_error(color);
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut display, &closest);
+
+        Image::new(&raw, Point::new(1, 1))
+            .draw(&mut target)
+            .unwrap();
+        Image::new(&raw, Point::new(3, 0))
+            .draw(&mut target)
+            .unwrap();
+
+        // each image is dithered on its own, starting from its top left pixel
+        display.assert_pattern(&["   WK", " WKKW", " KW  "]);
+    }
+
+    #[test]
+    fn test_slice_target() {
+        use embedded_graphics::image::{Image, ImageRaw};
+
+        const NARROW: [u8; 3 * 4 * 3] = [0x80; 3 * 4 * 3];
+        const WIDE: [u8; 3 * 9 * 2] = [0x80; 3 * 9 * 2];
+
+        let closest = |color| BLACK_WHITE.with_error(color);
+
+        let mut expected = MockDisplay::<Rgb888>::new();
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut expected, &closest);
+        Image::new(&ImageRaw::<Rgb888>::new(&NARROW, 4), Point::new(1, 2))
+            .draw(&mut target)
+            .unwrap();
+        Image::new(&ImageRaw::<Rgb888>::new(&WIDE, 9), Point::new(30, 10))
+            .draw(&mut target)
+            .unwrap();
+
+        // one buffer good for anything up to 9 wide does the same, wherever on the display it goes
+        let mut display = MockDisplay::<Rgb888>::new();
+        let mut buffer = [QuantizationError::default(); 10];
+        let mut target = SliceDitherTarget::new(&mut display, &closest, &mut buffer).unwrap();
+        assert_eq!(target.max_width(), 9);
+        Image::new(&ImageRaw::<Rgb888>::new(&NARROW, 4), Point::new(1, 2))
+            .draw(&mut target)
+            .unwrap();
+        Image::new(&ImageRaw::<Rgb888>::new(&WIDE, 9), Point::new(30, 10))
+            .draw(&mut target)
+            .unwrap();
+
+        display.assert_eq(&expected);
+    }
+
+    #[test]
+    fn test_short_streams() {
+        let closest = |color| BLACK_WHITE.with_error(color);
+
+        let mut display = MockDisplay::<Rgb888>::new();
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut display, &closest);
+        target.draw_iter([]).unwrap();
+        target
+            .fill_contiguous(&Rectangle::zero(), [GREY; 4])
+            .unwrap();
+        target
+            .fill_solid(&Rectangle::new(Point::new(5, 5), Size::new(0, 3)), GREY)
+            .unwrap();
+        assert_eq!(display, MockDisplay::new());
+
+        // fewer pixels than a row's worth
+        let mut display = MockDisplay::<Rgb888>::new();
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut display, &closest);
+        target.draw_iter([Pixel(Point::new(2, 0), GREY)]).unwrap();
+        target
+            .fill_contiguous(&Rectangle::new(Point::new(0, 1), Size::new(2, 1)), [GREY])
+            .unwrap();
+        Circle::new(Point::new(0, 3), 1)
+            .into_styled(PrimitiveStyle::with_fill(GREY))
+            .draw(&mut target)
+            .unwrap();
+        display.assert_pattern(&["  W", "W  ", "   ", "W  "]);
+
+        // and through the smallest possible buffer
+        let mut display = MockDisplay::<Rgb888>::new();
+        let mut buffer = [QuantizationError::default(); 2];
+        let mut target = SliceDitherTarget::new(&mut display, &closest, &mut buffer).unwrap();
+        target.draw_iter([]).unwrap();
+        target.draw_iter([Pixel(Point::new(0, 0), GREY)]).unwrap();
+        target
+            .fill_solid(&Rectangle::new(Point::new(0, 1), Size::new(1, 2)), GREY)
+            .unwrap();
+        // the one pixel wide column below carries on from the pixel above it
+        display.assert_pattern(&["W", "K", "W"]);
+    }
+
+    // counts what reaches the display, however it is drawn
+    struct Counter(usize);
+
+    impl DrawTarget for Counter {
+        type Color = Rgb888;
+        type Error = core::convert::Infallible;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Self::Color>>,
+        {
+            self.0 += pixels.into_iter().count();
+            Ok(())
+        }
+    }
+
+    impl OriginDimensions for Counter {
+        fn size(&self) -> Size {
+            Size::new(64, 64)
+        }
+    }
+
+    #[test]
+    fn test_every_pixel_drawn() {
+        let closest = |color| BLACK_WHITE.with_error(color);
+        let mut counter = Counter(0);
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut counter, &closest);
+
+        let mut expected = 0;
+        for (size, point) in [
+            (Size::new(64, 64), Point::zero()),
+            (Size::new(64, 1), Point::new(0, 63)),
+            (Size::new(5, 3), Point::new(58, 60)),
+            (Size::new(1, 1), Point::new(3, 3)),
+        ] {
+            let area = Rectangle::new(point, size);
+            target.fill_solid(&area, GREY).unwrap();
+            target
+                .fill_contiguous(&area, core::iter::repeat(GREY))
+                .unwrap();
+            target
+                .draw_iter(area.points().map(|point| Pixel(point, GREY)))
+                .unwrap();
+            expected += 3 * area.points().count();
+        }
+        assert_eq!(counter.0, expected);
+
+        // including the last row of a full screen
+        let mut display = MockDisplay::<Rgb888>::new();
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut display, &closest);
+        target.clear(GREY).unwrap();
+        for point in display.bounding_box().points() {
+            assert!(display.get_pixel(point).is_some());
+        }
+    }
+
+    #[test]
+    fn test_bands() {
+        use embedded_graphics::image::{Image, ImageRaw};
+
+        // a gradient, so any seam would show
+        let mut data = [0u8; 3 * 12 * 8];
+        for (i, pixel) in data.chunks_mut(3).enumerate() {
+            pixel.fill((i * 2) as u8);
+        }
+        let closest = |color| BLACK_WHITE.with_error(color);
+
+        let mut expected = MockDisplay::<Rgb888>::new();
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut expected, &closest);
+        Image::new(&ImageRaw::<Rgb888>::new(&data, 12), Point::new(4, 2))
+            .draw(&mut target)
+            .unwrap();
+
+        // the same image in bands of three rows
+        let mut display = MockDisplay::<Rgb888>::new();
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut display, &closest);
+        for (i, band) in data.chunks(3 * 12 * 3).enumerate() {
+            Image::new(
+                &ImageRaw::<Rgb888>::new(band, 12),
+                Point::new(4, 2 + 3 * i as i32),
+            )
+            .draw(&mut target)
+            .unwrap();
+        }
+        display.assert_eq(&expected);
+
+        // and as raw pixels a row at a time
+        let mut display = MockDisplay::<Rgb888>::new();
+        let mut target: DitherTarget<'_, _, Rgb888, _, 12> =
+            DitherTarget::new(&mut display, &closest);
+        for (y, row) in data.chunks(3 * 12).enumerate() {
+            target
+                .draw_iter(row.chunks(3).enumerate().map(|(x, pixel)| {
+                    Pixel(
+                        Point::new(x as i32, y as i32),
+                        Rgb888::new(pixel[0], pixel[1], pixel[2]),
+                    )
+                }))
+                .unwrap();
+        }
+        let mut whole = MockDisplay::<Rgb888>::new();
+        Image::new(&ImageRaw::<Rgb888>::new(&data, 12), Point::zero())
+            .draw(&mut DitherTarget::<'_, _, Rgb888, _, 12>::new(
+                &mut whole, &closest,
+            ))
+            .unwrap();
+        display.assert_eq(&whole);
+
+        // solid bands, the white one in between still passing on what the grey above it owes
+        let bands = [GREY, Rgb888::WHITE, GREY];
+        let mut expected = MockDisplay::<Rgb888>::new();
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut expected, &closest);
+        target
+            .fill_contiguous(
+                &Rectangle::new(Point::zero(), Size::new(4, 3)),
+                bands.iter().flat_map(|color| [*color; 4]),
+            )
+            .unwrap();
+
+        let mut display = MockDisplay::<Rgb888>::new();
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut display, &closest);
+        for (y, color) in bands.into_iter().enumerate() {
+            target
+                .fill_solid(
+                    &Rectangle::new(Point::new(0, y as i32), Size::new(4, 1)),
+                    color,
+                )
+                .unwrap();
+        }
+        display.assert_eq(&expected);
+    }
+
+    #[test]
+    fn test_filled_circle() {
+        // embedded-graphics fills a circle a scanline at a time, error has to carry from one to the next
+        let mut display = MockDisplay::<Rgb888>::new();
+        let closest = |color| BLACK_WHITE.with_error(color);
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut display, &closest);
+
+        let circle = Circle::new(Point::new(10, 10), 40);
+        circle
+            .into_styled(PrimitiveStyle::with_fill(Rgb888::new(0x40, 0x40, 0x40)))
+            .draw(&mut target)
+            .unwrap();
+
+        let white = circle
+            .points()
+            .filter(|point| display.get_pixel(*point) == Some(Rgb888::WHITE))
+            .count();
+        let total = circle.points().count();
+        assert!(white * 100 > total * 22 && white * 100 < total * 28);
+    }
+
+    #[test]
+    fn test_reset() {
+        let closest = |color| BLACK_WHITE.with_error(color);
+        let top = Rectangle::new(Point::new(0, 0), Size::new(2, 1));
+        let bottom = Rectangle::new(Point::new(0, 1), Size::new(2, 1));
+
+        // carried over from the band above, 128 - 40 + 13 goes black and 128 - 8 + 22 + 44 white
+        let mut display = MockDisplay::<Rgb888>::new();
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut display, &closest);
+        target.fill_solid(&top, GREY).unwrap();
+        target.fill_solid(&bottom, GREY).unwrap();
+        display.assert_pattern(&["WK", "KW"]);
+
+        // forgotten, the second band starts afresh
+        let mut display = MockDisplay::<Rgb888>::new();
+        let mut target: DitherTarget<'_, _, Rgb888, _, 64> =
+            DitherTarget::new(&mut display, &closest);
+        target.fill_solid(&top, GREY).unwrap();
+        target.reset();
+        target.fill_solid(&bottom, GREY).unwrap();
+        display.assert_pattern(&["WK", "WK"]);
+    }
+}
@@ -0,0 +1,379 @@
+// 3D colour lookup tables, for grading colours before they are dithered
+// same lattice as ColorCube (N samples spread evenly over 0..=255 per axis) but holding rgb,
+// and lookups interpolate between the 8 surrounding samples rather than taking the nearest
+use crate::lattice::sample;
+use embedded_graphics_core::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Size},
+    pixelcolor::{Rgb888, RgbColor},
+    primitives::Rectangle,
+    Pixel,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LutError {
+    // LUT_3D_SIZE or the number of Hald pixels doesn't match N
+    SizeMismatch,
+    // fewer or more table entries than N * N * N
+    WrongLength,
+    // a line that couldn't be parsed, 1 based
+    Parse(usize),
+    // something valid in the format that isn't handled, like LUT_1D_SIZE
+    Unsupported(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Lut<const N: usize>([[[Rgb888; N]; N]; N]);
+
+impl<const N: usize> Lut<N> {
+    // the lut that changes nothing
+    pub fn identity() -> Self {
+        const { assert!(N >= 2, "a lut needs at least 2 samples per axis") };
+
+        let mut table = [[[Rgb888::default(); N]; N]; N];
+        for (r, plane) in table.iter_mut().enumerate() {
+            for (g, row) in plane.iter_mut().enumerate() {
+                for (b, entry) in row.iter_mut().enumerate() {
+                    *entry = Rgb888::new(sample(r, N), sample(g, N), sample(b, N));
+                }
+            }
+        }
+
+        Self(table)
+    }
+
+    // Adobe/Resolve .cube text, red varying fastest
+    // DOMAIN_MIN and DOMAIN_MAX are honoured, TITLE and comments are skipped
+    pub fn from_cube(source: &str) -> Result<Self, LutError> {
+        const { assert!(N >= 2, "a lut needs at least 2 samples per axis") };
+
+        fn triple(
+            line: usize,
+            words: &mut core::str::SplitWhitespace,
+        ) -> Result<[f32; 3], LutError> {
+            let mut result = [0.0; 3];
+            for value in result.iter_mut() {
+                *value = words
+                    .next()
+                    .and_then(|word| word.parse().ok())
+                    .ok_or(LutError::Parse(line))?;
+            }
+            if words.next().is_some() {
+                return Err(LutError::Parse(line));
+            }
+            Ok(result)
+        }
+
+        let mut table = [[[Rgb888::default(); N]; N]; N];
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut entries = 0;
+
+        for (line, text) in source.lines().enumerate() {
+            let line = line + 1;
+            let mut words = text.split_whitespace();
+            match words.next() {
+                None => {}
+                Some(word) if word.starts_with('#') || word == "TITLE" => {}
+                Some("LUT_3D_SIZE") => {
+                    let n: usize = words
+                        .next()
+                        .and_then(|word| word.parse().ok())
+                        .ok_or(LutError::Parse(line))?;
+                    if n != N {
+                        return Err(LutError::SizeMismatch);
+                    }
+                    size = Some(n);
+                }
+                Some("DOMAIN_MIN") => domain_min = triple(line, &mut words)?,
+                Some("DOMAIN_MAX") => domain_max = triple(line, &mut words)?,
+                Some("LUT_1D_SIZE") | Some("LUT_3D_INPUT_RANGE") | Some("LUT_1D_INPUT_RANGE") => {
+                    return Err(LutError::Unsupported(line))
+                }
+                Some(_) => {
+                    let value = triple(line, &mut text.split_whitespace())?;
+
+                    if size.is_none() {
+                        return Err(LutError::Parse(line));
+                    }
+                    if entries == N * N * N {
+                        return Err(LutError::WrongLength);
+                    }
+
+                    let mut channels = [0u8; 3];
+                    for (i, channel) in channels.iter_mut().enumerate() {
+                        let normalized =
+                            (value[i] - domain_min[i]) / (domain_max[i] - domain_min[i]);
+                        *channel = (normalized.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+                    }
+                    let (r, g, b) = (entries % N, (entries / N) % N, entries / (N * N));
+                    table[r][g][b] = Rgb888::new(channels[0], channels[1], channels[2]);
+                    entries += 1;
+                }
+            }
+        }
+
+        if entries != N * N * N {
+            return Err(LutError::WrongLength);
+        }
+
+        Ok(Self(table))
+    }
+
+    // pixels of a Hald CLUT image in raster order, red varying fastest
+    // a level L Hald image is L^3 pixels square and holds an L^2 lut, so N is L * L
+    pub fn from_hald<I>(pixels: I) -> Result<Self, LutError>
+    where
+        I: IntoIterator<Item = Rgb888>,
+    {
+        const { assert!(N >= 2, "a lut needs at least 2 samples per axis") };
+
+        let mut table = [[[Rgb888::default(); N]; N]; N];
+        let mut pixels = pixels.into_iter();
+        for i in 0..N * N * N {
+            let (r, g, b) = (i % N, (i / N) % N, i / (N * N));
+            table[r][g][b] = pixels.next().ok_or(LutError::WrongLength)?;
+        }
+        if pixels.next().is_some() {
+            return Err(LutError::WrongLength);
+        }
+
+        Ok(Self(table))
+    }
+
+    // trilinear interpolation between the 8 samples surrounding color
+    pub fn apply(&self, color: Rgb888) -> Rgb888 {
+        // whole sample index and a fraction of the way to the next one, out of 255
+        let position = |channel: u8| {
+            let scaled = channel as usize * (N - 1);
+            let i = (scaled / 255).min(N - 2);
+            (i, (scaled - i * 255) as u32)
+        };
+        let lerp = |a: u32, b: u32, t: u32| a * (255 - t) + b * t;
+
+        let (r, tr) = position(color.r());
+        let (g, tg) = position(color.g());
+        let (b, tb) = position(color.b());
+
+        let channel = |get: fn(Rgb888) -> u8| {
+            let at = |i: usize, j: usize, k: usize| get(self.0[r + i][g + j][b + k]) as u32;
+
+            let c00 = lerp(at(0, 0, 0), at(1, 0, 0), tr);
+            let c01 = lerp(at(0, 0, 1), at(1, 0, 1), tr);
+            let c10 = lerp(at(0, 1, 0), at(1, 1, 0), tr);
+            let c11 = lerp(at(0, 1, 1), at(1, 1, 1), tr);
+            let c0 = c00 * (255 - tg) + c10 * tg;
+            let c1 = c01 * (255 - tg) + c11 * tg;
+
+            ((c0 as u64 * (255 - tb) as u64 + c1 as u64 * tb as u64 + 255 * 255 * 255 / 2)
+                / (255 * 255 * 255)) as u8
+        };
+
+        Rgb888::new(channel(|c| c.r()), channel(|c| c.g()), channel(|c| c.b()))
+    }
+}
+
+// applies a colour transform (a Lut, or anything else) on the way through to another target
+// typically sits in front of a DitherTarget so assets are graded before being dithered
+pub struct Transform<'a, Display, F>
+where
+    Display: DrawTarget + OriginDimensions,
+    F: Fn(Display::Color) -> Display::Color,
+{
+    display: &'a mut Display,
+    transform_fn: &'a F,
+}
+
+impl<'a, Display, F> Transform<'a, Display, F>
+where
+    Display: DrawTarget + OriginDimensions,
+    F: Fn(Display::Color) -> Display::Color,
+{
+    pub fn new(display: &'a mut Display, transform_fn: &'a F) -> Self {
+        Self {
+            display,
+            transform_fn,
+        }
+    }
+}
+
+impl<'a, Display, F> OriginDimensions for Transform<'a, Display, F>
+where
+    Display: DrawTarget + OriginDimensions,
+    F: Fn(Display::Color) -> Display::Color,
+{
+    fn size(&self) -> Size {
+        self.display.size()
+    }
+}
+
+impl<'a, Display, F> DrawTarget for Transform<'a, Display, F>
+where
+    Display: DrawTarget + OriginDimensions,
+    F: Fn(Display::Color) -> Display::Color,
+{
+    type Color = Display::Color;
+    type Error = Display::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        self.display.draw_iter(
+            pixels
+                .into_iter()
+                .map(|pixel| Pixel(pixel.0, (self.transform_fn)(pixel.1))),
+        )
+    }
+
+    // passed through so whatever the wrapped target does with areas still applies
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        self.display
+            .fill_contiguous(area, colors.into_iter().map(self.transform_fn))
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.display.fill_solid(area, (self.transform_fn)(color))
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        let area = self.display.bounding_box();
+        self.fill_solid(&area, color)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use super::*;
+    use embedded_graphics::mock_display::MockDisplay;
+    use embedded_graphics_core::geometry::Point;
+    use std::borrow::ToOwned;
+
+    // swaps red and blue, so getting the order of entries wrong shows up
+    const SWAP: &str = "# red and blue swapped
+TITLE \"swap\"
+LUT_3D_SIZE 2
+DOMAIN_MIN 0.0 0.0 0.0
+DOMAIN_MAX 1.0 1.0 1.0
+
+0.0 0.0 0.0
+0.0 0.0 1.0
+0.0 1.0 0.0
+0.0 1.0 1.0
+1.0 0.0 0.0
+1.0 0.0 1.0
+1.0 1.0 0.0
+1.0 1.0 1.0
+";
+
+    #[test]
+    fn test_identity() {
+        let coarse = Lut::<2>::identity();
+        let fine = Lut::<17>::identity();
+
+        for v in (0..=255).step_by(5) {
+            let color = Rgb888::new(v, 255 - v, v / 2);
+            assert_eq!(coarse.apply(color), color);
+
+            let applied = fine.apply(color);
+            assert!(applied.r().abs_diff(color.r()) <= 1);
+            assert!(applied.g().abs_diff(color.g()) <= 1);
+            assert!(applied.b().abs_diff(color.b()) <= 1);
+        }
+    }
+
+    #[test]
+    fn test_cube() {
+        let lut = Lut::<2>::from_cube(SWAP).unwrap();
+
+        assert_eq!(
+            lut.apply(Rgb888::new(0xff, 0x00, 0x00)),
+            Rgb888::new(0x00, 0x00, 0xff)
+        );
+        assert_eq!(
+            lut.apply(Rgb888::new(0x20, 0x40, 0x60)),
+            Rgb888::new(0x60, 0x40, 0x20)
+        );
+
+        let doubled = "LUT_3D_SIZE 2\nDOMAIN_MAX 2 2 2\n".to_owned()
+            + &SWAP
+                .lines()
+                .skip(6)
+                .collect::<std::vec::Vec<_>>()
+                .join("\n");
+        assert_eq!(
+            Lut::<2>::from_cube(&doubled)
+                .unwrap()
+                .apply(Rgb888::new(0xff, 0xff, 0xff)),
+            Rgb888::new(0x80, 0x80, 0x80)
+        );
+    }
+
+    #[test]
+    fn test_cube_errors() {
+        assert_eq!(
+            Lut::<3>::from_cube(SWAP).unwrap_err(),
+            LutError::SizeMismatch
+        );
+        assert_eq!(
+            Lut::<2>::from_cube(SWAP.strip_suffix("1.0 1.0 1.0\n").unwrap()).unwrap_err(),
+            LutError::WrongLength
+        );
+        assert_eq!(
+            Lut::<2>::from_cube(&(SWAP.to_owned() + "1.0 1.0 1.0\n")).unwrap_err(),
+            LutError::WrongLength
+        );
+        assert_eq!(
+            Lut::<2>::from_cube(&SWAP.replace("0.0 1.0 1.0", "0.0 one 1.0")).unwrap_err(),
+            LutError::Parse(10)
+        );
+        assert_eq!(
+            Lut::<2>::from_cube("LUT_1D_SIZE 2\n").unwrap_err(),
+            LutError::Unsupported(1)
+        );
+    }
+
+    #[test]
+    fn test_hald() {
+        // level 2 hald, an 8 by 8 image holding a 4 cube
+        let pixels = (0..64)
+            .map(|i| Rgb888::new(sample(i % 4, 4), sample((i / 4) % 4, 4), sample(i / 16, 4)));
+
+        let lut = Lut::<4>::from_hald(pixels.clone()).unwrap();
+        assert_eq!(lut.0, Lut::<4>::identity().0);
+
+        assert_eq!(
+            Lut::<4>::from_hald(pixels.clone().take(63)).unwrap_err(),
+            LutError::WrongLength
+        );
+        assert_eq!(
+            Lut::<3>::from_hald(pixels).unwrap_err(),
+            LutError::WrongLength
+        );
+    }
+
+    #[test]
+    fn test_transform() {
+        let lut = Lut::<2>::from_cube(SWAP).unwrap();
+        let mut display = MockDisplay::<Rgb888>::new();
+        let binding = |color| lut.apply(color);
+        let mut transform = Transform::new(&mut display, &binding);
+
+        transform
+            .fill_solid(
+                &Rectangle::new(Point::zero(), Size::new(2, 1)),
+                Rgb888::new(0xff, 0x00, 0x00),
+            )
+            .unwrap();
+        transform
+            .draw_iter([Pixel(Point::new(0, 1), Rgb888::new(0x00, 0xff, 0xff))])
+            .unwrap();
+
+        display.assert_pattern(&["BB", "Y "]);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::QuantizationError;
+use embedded_graphics_core::pixelcolor::Rgb888;
+
+// a fixed set of display colours and the rgb each one actually shows as
+// nearest colour search is a linear scan using whichever metric from crate::distance (or elsewhere)
+// the palette was built with
+#[derive(Debug, Clone)]
+pub struct Palette<C, const N: usize>
+where
+    C: Copy,
+{
+    entries: [(C, Rgb888); N],
+    distance: fn(Rgb888, Rgb888) -> u32,
+}
+
+impl<C, const N: usize> Palette<C, N>
+where
+    C: Copy,
+{
+    pub const fn new(entries: [(C, Rgb888); N], distance: fn(Rgb888, Rgb888) -> u32) -> Self {
+        const { assert!(N > 0, "a palette needs at least one colour") };
+
+        Self { entries, distance }
+    }
+
+    pub fn entries(&self) -> &[(C, Rgb888); N] {
+        &self.entries
+    }
+
+    // rgb of a display colour, for going back the other way
+    pub fn rgb(&self, color: C) -> Option<Rgb888>
+    where
+        C: PartialEq,
+    {
+        self.entries
+            .iter()
+            .find(|(c, _)| *c == color)
+            .map(|(_, rgb)| *rgb)
+    }
+
+    pub fn nearest<I>(&self, color: I) -> (C, Rgb888)
+    where
+        I: Into<Rgb888>,
+    {
+        self.entries[self.nearest_index(color)]
+    }
+
+    // position in entries of the nearest colour
+    pub fn nearest_index<I>(&self, color: I) -> usize
+    where
+        I: Into<Rgb888>,
+    {
+        let color = color.into();
+
+        (0..N)
+            .min_by_key(|i| (self.distance)(color, self.entries[*i].1))
+            .unwrap()
+    }
+
+    // suitable for use as DitherTarget's closest_color_fn
+    // error is measured against the rgb of the chosen palette entry
+    pub fn with_error<I>(&self, color: I) -> (C, QuantizationError)
+    where
+        I: Into<Rgb888>,
+    {
+        let color = color.into();
+        let (closest, rgb) = self.nearest(color);
+
+        (
+            closest,
+            QuantizationError::from(color) - QuantizationError::from(rgb),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::distance;
+
+    const PALETTE: Palette<char, 3> = Palette::new(
+        [
+            ('k', Rgb888::new(0x00, 0x00, 0x00)),
+            ('w', Rgb888::new(0xff, 0xff, 0xff)),
+            ('r', Rgb888::new(0xff, 0x00, 0x00)),
+        ],
+        distance::redmean,
+    );
+
+    #[test]
+    fn test_nearest() {
+        assert_eq!(PALETTE.nearest(Rgb888::new(0x10, 0x10, 0x10)).0, 'k');
+        assert_eq!(PALETTE.nearest(Rgb888::new(0xf0, 0xf0, 0xf0)).0, 'w');
+        assert_eq!(PALETTE.nearest(Rgb888::new(0xc0, 0x20, 0x10)).0, 'r');
+        assert_eq!(PALETTE.rgb('r'), Some(Rgb888::new(0xff, 0x00, 0x00)));
+        assert_eq!(PALETTE.rgb('g'), None);
+    }
+
+    #[test]
+    fn test_error() {
+        assert_eq!(
+            PALETTE.with_error(Rgb888::new(0x01, 0x02, 0x03)),
+            ('k', QuantizationError::new((1, 2, 3)))
+        );
+        assert_eq!(
+            PALETTE.with_error(Rgb888::new(0xf0, 0x10, 0x20)),
+            ('r', QuantizationError::new((-15, 16, 32)))
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{diffusion::Diffusion, wrapping_vec::WrappingSlice, DitherError, QuantizationError};
+use core::convert::Infallible;
+use embedded_graphics_core::{geometry::Point, pixelcolor::Rgb888};
+
+// the same dithering as DitherTarget, for plain buffers without a display in sight
+// (asset pipelines, image decoders)
+// buffer holds the error still to be diffused and needs at least width + 1 cells
+
+fn diffusion(
+    width: usize,
+    buffer: &mut [QuantizationError],
+) -> Result<Diffusion<WrappingSlice<'_, QuantizationError>>, DitherError<Infallible>> {
+    if width == 0 || buffer.len() <= width {
+        return Err(DitherError::SizeMismatch);
+    }
+
+    let mut diffusion = Diffusion::new(WrappingSlice::new(buffer));
+    diffusion.restart(Point::zero(), width);
+
+    Ok(diffusion)
+}
+
+// src is rows width long, out gets closest_color_fn's colour for each pixel
+pub fn dither<C, D, F>(
+    src: &[C],
+    width: usize,
+    closest_color_fn: &F,
+    out: &mut [D],
+    buffer: &mut [QuantizationError],
+) -> Result<(), DitherError<Infallible>>
+where
+    C: Copy + From<QuantizationError> + Into<QuantizationError>,
+    F: Fn(C) -> (D, QuantizationError),
+{
+    if out.len() != src.len() || !src.len().is_multiple_of(width.max(1)) {
+        return Err(DitherError::SizeMismatch);
+    }
+    let mut diffusion = diffusion(width, buffer)?;
+
+    for (color, out) in src.iter().zip(out) {
+        *out = diffusion.diffuse(*color, closest_color_fn);
+    }
+
+    Ok(())
+}
+
+// src is packed r, g, b bytes, out gets a byte per pixel (a palette index, say)
+pub fn dither_rgb888<F>(
+    src: &[u8],
+    width: usize,
+    height: usize,
+    closest_color_fn: &F,
+    out: &mut [u8],
+    buffer: &mut [QuantizationError],
+) -> Result<(), DitherError<Infallible>>
+where
+    F: Fn(Rgb888) -> (u8, QuantizationError),
+{
+    if src.len() != width * height * 3 || out.len() != width * height {
+        return Err(DitherError::SizeMismatch);
+    }
+    let mut diffusion = diffusion(width, buffer)?;
+
+    for (rgb, out) in src.chunks_exact(3).zip(out) {
+        *out = diffusion.diffuse(Rgb888::new(rgb[0], rgb[1], rgb[2]), closest_color_fn);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::BLACK_WHITE_INDEXED;
+    use embedded_graphics_core::pixelcolor::RgbColor;
+
+    #[test]
+    fn test_dither_rgb888() {
+        let closest = |color| BLACK_WHITE_INDEXED.with_error(color);
+        let mut buffer = [QuantizationError::default(); 8];
+
+        let src = [0x80; 2 * 2 * 3];
+        let mut out = [0xff; 2 * 2];
+        dither_rgb888(&src, 2, 2, &closest, &mut out, &mut buffer).unwrap();
+        assert_eq!(out, [1, 0, 0, 1]);
+
+        // the same through the generic version
+        let src = [Rgb888::new(0x80, 0x80, 0x80); 4];
+        let mut generic = [0xff; 4];
+        dither(&src, 2, &closest, &mut generic, &mut buffer).unwrap();
+        assert_eq!(generic, out);
+    }
+
+    #[test]
+    fn test_sizes() {
+        let closest = |color| BLACK_WHITE_INDEXED.with_error(color);
+        let mut buffer = [QuantizationError::default(); 3];
+        let src = [0x80; 3 * 3 * 3];
+
+        // buffer too short for the width
+        assert_eq!(
+            dither_rgb888(&src, 3, 3, &closest, &mut [0; 9], &mut buffer),
+            Err(DitherError::SizeMismatch)
+        );
+        // src and out disagreeing with width and height
+        assert_eq!(
+            dither_rgb888(&src, 2, 3, &closest, &mut [0; 6], &mut buffer),
+            Err(DitherError::SizeMismatch)
+        );
+        assert_eq!(
+            dither_rgb888(&src[..6], 2, 1, &closest, &mut [0; 3], &mut buffer),
+            Err(DitherError::SizeMismatch)
+        );
+        assert_eq!(
+            dither(&[Rgb888::BLACK; 3], 2, &closest, &mut [0; 3], &mut buffer),
+            Err(DitherError::SizeMismatch)
+        );
+
+        // nothing at all is fine
+        dither_rgb888(&[], 2, 0, &closest, &mut [], &mut buffer).unwrap();
+    }
+}
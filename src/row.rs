@@ -0,0 +1,140 @@
+use crate::{
+    accumulator::Accumulator,
+    diffusion::Diffusion,
+    kernel::{FloydSteinberg, Kernel},
+    wrapping_vec::WrappingVec,
+    DitherError, QuantizationError,
+};
+use core::convert::Infallible;
+use embedded_graphics_core::geometry::Point;
+
+// dithers an image a row at a time as it arrives (from a decoder, a camera), without a framebuffer
+// only the error owed to the next row is kept, WIDTH + 1 cells however tall the image
+// width is the image's and can be anything up to WIDTH
+pub struct RowDitherer<'a, C, D, F, const WIDTH: usize, K = FloydSteinberg>
+where
+    C: Copy + From<Accumulator> + Into<Accumulator>,
+    F: Fn(C) -> (D, QuantizationError),
+    K: Kernel,
+{
+    closest_color_fn: &'a F,
+    diffusion: Diffusion<WrappingVec<Accumulator, WIDTH>, K>,
+    width: usize,
+    rows: usize,
+    phantom: core::marker::PhantomData<(C, D)>,
+}
+
+impl<'a, C, D, F, const WIDTH: usize, K> RowDitherer<'a, C, D, F, WIDTH, K>
+where
+    C: Copy + From<Accumulator> + Into<Accumulator>,
+    F: Fn(C) -> (D, QuantizationError),
+    K: Kernel,
+{
+    pub fn new(width: usize, closest_color_fn: &'a F) -> Result<Self, DitherError<Infallible>> {
+        if width == 0 || width > WIDTH {
+            return Err(DitherError::SizeMismatch);
+        }
+
+        let mut diffusion = Diffusion::new(WrappingVec::new(&mut core::iter::empty()));
+        diffusion.restart(Point::zero(), width);
+
+        Ok(Self {
+            closest_color_fn,
+            diffusion,
+            width,
+            rows: 0,
+            phantom: core::marker::PhantomData,
+        })
+    }
+
+    // dithers the next row, which has to be exactly width long
+    // the row's colours come out as the iterator is consumed, any it isn't consumed for diffuse nothing
+    pub fn push_row<'r>(
+        &'r mut self,
+        row: &'r [C],
+    ) -> Result<impl Iterator<Item = D> + use<'a, 'r, C, D, F, WIDTH, K>, DitherError<Infallible>>
+    {
+        if row.len() != self.width {
+            return Err(DitherError::SizeMismatch);
+        }
+
+        self.diffusion.seek(self.rows * self.width);
+        self.rows += 1;
+
+        let closest_color_fn = self.closest_color_fn;
+        let diffusion = &mut self.diffusion;
+
+        Ok(row
+            .iter()
+            .map(move |color| diffusion.diffuse(*color, closest_color_fn)))
+    }
+
+    // how many rows have been pushed since the start
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    // back to the top, for the next image
+    pub fn reset(&mut self) {
+        self.diffusion.restart(Point::zero(), self.width);
+        self.rows = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        raw,
+        test_util::{gradient, BLACK_WHITE_INDEXED},
+    };
+    use embedded_graphics_core::pixelcolor::Rgb888;
+
+    #[test]
+    fn test_rows() {
+        let closest = |color| BLACK_WHITE_INDEXED.with_error(color);
+        let image = gradient::<{ 10 * 6 }>();
+
+        let mut expected = [0; 10 * 6];
+        let mut buffer = [QuantizationError::default(); 11];
+        raw::dither(&image, 10, &closest, &mut expected, &mut buffer).unwrap();
+
+        let mut ditherer: RowDitherer<'_, _, _, _, 16> = RowDitherer::new(10, &closest).unwrap();
+        let mut out = [0; 10 * 6];
+        for (row, out) in image.chunks(10).zip(out.chunks_mut(10)) {
+            for (out, pixel) in out.iter_mut().zip(ditherer.push_row(row).unwrap()) {
+                *out = pixel;
+            }
+        }
+        assert_eq!(ditherer.rows(), 6);
+        assert_eq!(out, expected);
+
+        // and again after a reset
+        ditherer.reset();
+        for (row, expected) in image.chunks(10).zip(expected.chunks(10)) {
+            assert!(ditherer.push_row(row).unwrap().eq(expected.iter().copied()));
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let closest = |color| BLACK_WHITE_INDEXED.with_error(color);
+        let image = gradient::<{ 10 * 6 }>();
+
+        assert!(matches!(
+            RowDitherer::<'_, Rgb888, _, _, 8>::new(10, &closest),
+            Err(DitherError::SizeMismatch)
+        ));
+
+        let mut ditherer: RowDitherer<'_, _, _, _, 16> = RowDitherer::new(10, &closest).unwrap();
+        assert!(matches!(
+            ditherer.push_row(&image[..9]),
+            Err(DitherError::SizeMismatch)
+        ));
+
+        // a row left unread still counts, the next lines up underneath it
+        let _ = ditherer.push_row(&image[..10]).unwrap();
+        assert_eq!(ditherer.push_row(&image[10..20]).unwrap().count(), 10);
+        assert_eq!(ditherer.rows(), 2);
+    }
+}
@@ -0,0 +1,31 @@
+// palettes and images shared by the tests
+
+use crate::{distance, palette::Palette};
+use embedded_graphics_core::pixelcolor::{Rgb888, RgbColor};
+
+// for drawing to a display of the same colours
+pub const BLACK_WHITE: Palette<Rgb888, 2> = Palette::new(
+    [
+        (Rgb888::BLACK, Rgb888::BLACK),
+        (Rgb888::WHITE, Rgb888::WHITE),
+    ],
+    distance::euclidean,
+);
+
+// for dithering to 0 and 1
+pub const BLACK_WHITE_INDEXED: Palette<u8, 2> = Palette::new(
+    [(0, Rgb888::BLACK), (1, Rgb888::WHITE)],
+    distance::euclidean,
+);
+
+pub const GREY: Rgb888 = Rgb888::new(0x80, 0x80, 0x80);
+
+// black to white in raster order, so error gets diffused everywhere and any seam would show
+pub fn gradient<const LEN: usize>() -> [Rgb888; LEN] {
+    let mut image = [Rgb888::BLACK; LEN];
+    for (i, pixel) in image.iter_mut().enumerate() {
+        let v = (i * 255 / (LEN - 1)) as u8;
+        *pixel = Rgb888::new(v, v, v);
+    }
+    image
+}
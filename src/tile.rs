@@ -0,0 +1,277 @@
+use crate::{
+    accumulator::Accumulator,
+    kernel::{self, FloydSteinberg, Kernel},
+    DitherError, QuantizationError,
+};
+use core::convert::Infallible;
+use embedded_graphics_core::{
+    geometry::{Point, Size},
+    primitives::Rectangle,
+};
+
+// dithers a width x height image in T x T tiles (smaller along the right and bottom edges),
+// for displays that are written a window at a time
+// error crossing into a tile that hasn't been dithered yet is kept in edge buffers until it is:
+// a row of width cells along the top of each tile row and a column of height cells down the left of
+// each tile column, which is all that's needed as error only ever moves one pixel right or down
+// (kernels reaching further don't compile here)
+// tiles in raster order come out as if the whole image had been dithered at once, but for the
+// below left share at each tile's left edge which would go back into the tile before it
+// any other order works too, error into tiles already dithered just being dropped
+pub struct TileDitherer<'a, C, D, F, const T: usize, K = FloydSteinberg>
+where
+    C: Copy + From<Accumulator> + Into<Accumulator>,
+    F: Fn(C) -> (D, QuantizationError),
+    K: Kernel,
+{
+    closest_color_fn: &'a F,
+    width: usize,
+    height: usize,
+    edges: &'a mut [QuantizationError],
+    phantom: core::marker::PhantomData<(C, D, K)>,
+}
+
+impl<'a, C, D, F, const T: usize, K> TileDitherer<'a, C, D, F, T, K>
+where
+    C: Copy + From<Accumulator> + Into<Accumulator>,
+    F: Fn(C) -> (D, QuantizationError),
+    K: Kernel,
+{
+    pub fn new(
+        width: usize,
+        height: usize,
+        closest_color_fn: &'a F,
+        edges: &'a mut [QuantizationError],
+    ) -> Result<Self, DitherError<Infallible>> {
+        if edges.len() != edges_len::<T>(width, height) {
+            return Err(DitherError::SizeMismatch);
+        }
+        edges.fill(QuantizationError::default());
+
+        Ok(Self {
+            closest_color_fn,
+            width,
+            height,
+            edges,
+            phantom: core::marker::PhantomData,
+        })
+    }
+
+    // how many tiles across and down
+    pub fn tiles(&self) -> (usize, usize) {
+        (self.width.div_ceil(T), self.height.div_ceil(T))
+    }
+
+    // where on the image a tile goes, for setting the display's window
+    pub fn area(&self, tile_x: usize, tile_y: usize) -> Rectangle {
+        let (x, y) = (tile_x * T, tile_y * T);
+
+        Rectangle::new(
+            Point::new(x as i32, y as i32),
+            Size::new(
+                T.min(self.width.saturating_sub(x)) as u32,
+                T.min(self.height.saturating_sub(y)) as u32,
+            ),
+        )
+    }
+
+    // forgets all error carried between tiles, for the next frame
+    pub fn reset(&mut self) {
+        self.edges.fill(QuantizationError::default());
+    }
+
+    fn down(&mut self, tile_y: usize, x: usize) -> &mut QuantizationError {
+        &mut self.edges[(tile_y - 1) * self.width + x]
+    }
+
+    fn across(&mut self, tile_x: usize, y: usize) -> &mut QuantizationError {
+        let rows = self.height.div_ceil(T);
+        &mut self.edges[(rows - 1) * self.width + (tile_x - 1) * self.height + y]
+    }
+
+    // dithers one tile, src and out being its pixels in raster order, each tile should be dithered once
+    pub fn dither_tile(
+        &mut self,
+        tile_x: usize,
+        tile_y: usize,
+        src: &[C],
+        out: &mut [D],
+    ) -> Result<(), DitherError<Infallible>> {
+        const {
+            assert!(
+                kernel::adjacent::<K>(),
+                "kernel reaches past the edge buffers"
+            )
+        };
+
+        let (columns, rows) = self.tiles();
+        if tile_x >= columns || tile_y >= rows {
+            return Err(DitherError::OutOfBounds);
+        }
+        let area = self.area(tile_x, tile_y);
+        let (tile_width, tile_height) = (area.size.width as usize, area.size.height as usize);
+        if src.len() != tile_width * tile_height || out.len() != src.len() {
+            return Err(DitherError::SizeMismatch);
+        }
+        let (x0, y0) = (tile_x * T, tile_y * T);
+
+        // error owed to the row being dithered and the one after it, by column within the tile
+        let mut below = [Accumulator::default(); T];
+        if tile_y > 0 {
+            for (x, owed) in below.iter_mut().take(tile_width).enumerate() {
+                *owed = *self.down(tile_y, x0 + x);
+            }
+        }
+
+        for y in 0..tile_height {
+            let mut this = core::mem::replace(&mut below, [Accumulator::default(); T]);
+            if tile_x > 0 {
+                this[0] += *self.across(tile_x, y0 + y);
+            }
+
+            for x in 0..tile_width {
+                let (dithered_color, quantization_error) =
+                    (self.closest_color_fn)(C::from(src[y * tile_width + x].into() + this[x]));
+                out[y * tile_width + x] = dithered_color;
+
+                // nothing carries past the edges of the image
+                for (to_x, dy, share) in kernel::shares::<K>(x0 + x, self.width, quantization_error)
+                {
+                    let to_y = y0 + y + dy;
+                    if to_y >= self.height {
+                        continue;
+                    }
+
+                    if to_y >= y0 + tile_height {
+                        // the tile row below, whichever tile that lands in
+                        *self.down(tile_y + 1, to_x) += share;
+                    } else if to_x >= x0 + tile_width {
+                        // the tile to the right, at most one column in
+                        *self.across(tile_x + 1, to_y) += share;
+                    } else if to_x < x0 {
+                        // the tile to the left, already dithered when going in raster order
+                    } else if dy == 0 {
+                        this[to_x - x0] += share;
+                    } else {
+                        below[to_x - x0] += share;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// cells of edge buffer needed for an image of the given size in T x T tiles
+pub const fn edges_len<const T: usize>(width: usize, height: usize) -> usize {
+    const { assert!(T > 0, "tiles need to be at least one pixel") };
+
+    let (columns, rows) = (width.div_ceil(T), height.div_ceil(T));
+
+    rows.saturating_sub(1) * width + columns.saturating_sub(1) * height
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        iter::Dither,
+        kernel::FalseFloydSteinberg,
+        test_util::{gradient, BLACK_WHITE_INDEXED},
+    };
+    use embedded_graphics_core::pixelcolor::{Rgb888, RgbColor};
+
+    const WIDTH: usize = 11;
+    const HEIGHT: usize = 9;
+
+    // dithers image tile by tile in the given order, reassembling the result
+    fn tiled<K: Kernel>(order: impl Iterator<Item = (usize, usize)>) -> [u8; WIDTH * HEIGHT] {
+        let closest = |color| BLACK_WHITE_INDEXED.with_error(color);
+        let image = gradient::<{ WIDTH * HEIGHT }>();
+
+        let mut edges = [QuantizationError::default(); edges_len::<4>(WIDTH, HEIGHT)];
+        let mut tiles: TileDitherer<'_, _, _, _, 4, K> =
+            TileDitherer::new(WIDTH, HEIGHT, &closest, &mut edges).unwrap();
+
+        let mut result = [0; WIDTH * HEIGHT];
+        for (tile_x, tile_y) in order {
+            let area = tiles.area(tile_x, tile_y);
+            let mut src = [Rgb888::BLACK; 16];
+            let mut out = [0; 16];
+            let len = area.size.width as usize * area.size.height as usize;
+
+            let points = (0..area.size.height as usize).flat_map(|y| {
+                (0..area.size.width as usize)
+                    .map(move |x| (area.top_left.x as usize + x, area.top_left.y as usize + y))
+            });
+            for (src, (x, y)) in src.iter_mut().zip(points.clone()) {
+                *src = image[y * WIDTH + x];
+            }
+            tiles
+                .dither_tile(tile_x, tile_y, &src[..len], &mut out[..len])
+                .unwrap();
+            for (out, (x, y)) in out.iter().zip(points) {
+                result[y * WIDTH + x] = *out;
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn test_raster_order() {
+        // without a below left share, tiles in raster order are exactly the whole image dithered
+        let closest = |color| BLACK_WHITE_INDEXED.with_error(color);
+        let mut buffer = [QuantizationError::default(); WIDTH + 1];
+        let whole = gradient::<{ WIDTH * HEIGHT }>()
+            .into_iter()
+            .dither(FalseFloydSteinberg, WIDTH, &closest, &mut buffer)
+            .unwrap();
+
+        let order = (0..3).flat_map(|y| (0..3).map(move |x| (x, y)));
+        assert!(tiled::<FalseFloydSteinberg>(order).into_iter().eq(whole));
+    }
+
+    #[test]
+    fn test_any_order() {
+        // backwards loses some error, but each tile still comes out dithered
+        let order = (0..3).rev().flat_map(|y| (0..3).rev().map(move |x| (x, y)));
+        let result = tiled::<FloydSteinberg>(order);
+
+        let white = result.iter().filter(|v| **v == 1).count();
+        let expected = gradient::<{ WIDTH * HEIGHT }>()
+            .iter()
+            .map(|c| c.r() as usize)
+            .sum::<usize>()
+            / 255;
+        assert!(white.abs_diff(expected) <= 6);
+    }
+
+    #[test]
+    fn test_errors() {
+        let closest = |color: Rgb888| BLACK_WHITE_INDEXED.with_error(color);
+        let mut edges = [QuantizationError::default(); 3];
+        assert!(matches!(
+            TileDitherer::<'_, _, _, _, 4>::new(WIDTH, HEIGHT, &closest, &mut edges),
+            Err(DitherError::SizeMismatch)
+        ));
+
+        // a single tile needs no edges
+        let mut tiles: TileDitherer<'_, _, _, _, 4> =
+            TileDitherer::new(3, 2, &closest, &mut []).unwrap();
+        assert_eq!(tiles.tiles(), (1, 1));
+        assert_eq!(
+            tiles.dither_tile(1, 0, &[], &mut []),
+            Err(DitherError::OutOfBounds)
+        );
+        assert_eq!(
+            tiles.dither_tile(0, 0, &[Rgb888::BLACK; 4], &mut [0; 4]),
+            Err(DitherError::SizeMismatch)
+        );
+        let mut out = [9; 6];
+        tiles
+            .dither_tile(0, 0, &[Rgb888::WHITE; 6], &mut out)
+            .unwrap();
+        assert_eq!(out, [1; 6]);
+    }
+}
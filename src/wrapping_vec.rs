@@ -1,8 +1,20 @@
 use core::ops::{Index, IndexMut};
 
+// a fixed number of cells indexed relative to a cursor, pushing writes the cell at the cursor and moves on
+// so after a push what was [1] is [0] and the pushed item is last
+pub trait Ring<X>: IndexMut<usize, Output = X> {
+    // most cells that can take part in wrapping
+    fn capacity(&self) -> usize;
+    // back to all default, wrapping after len cells
+    fn reset(&mut self, len: usize);
+    fn push(&mut self, item: X);
+}
+
 // weirdly carries N cells of v PLUS an extra v_n cell
 // this is a mechanism to hold N+1 without resorting to unstable const expr
 // complicates logic but at least it's somewhat hidden/isolated
+// only the first len of those N+1 cells take part in wrapping, so it can be used shorter than N+1
+#[derive(Clone)]
 pub struct WrappingVec<X, const N: usize>
 where
     X: Default + Copy,
@@ -10,6 +22,7 @@ where
     v: [X; N],
     v_n: X,
     cursor: usize,
+    len: usize,
 }
 
 impl<X, const N: usize> WrappingVec<X, N>
@@ -20,23 +33,47 @@ where
     where
         I: Iterator<Item = X>,
     {
+        // a source with fewer than N+1 items leaves the rest default
         let mut v = [Default::default(); N];
 
-        for item in v.iter_mut().take(N) {
-            *item = source_pixels.next().unwrap();
+        for (item, source) in v.iter_mut().zip(&mut *source_pixels) {
+            *item = source;
         }
-        let v_n: X = source_pixels.next().unwrap();
+        let v_n: X = source_pixels.next().unwrap_or_default();
+
+        Self {
+            v,
+            v_n,
+            cursor: 0,
+            len: N + 1,
+        }
+    }
+}
 
-        Self { v, v_n, cursor: 0 }
+impl<X, const N: usize> Ring<X> for WrappingVec<X, N>
+where
+    X: Default + Copy,
+{
+    fn capacity(&self) -> usize {
+        N + 1
     }
 
-    pub fn push(&mut self, item: X) {
+    fn reset(&mut self, len: usize) {
+        assert!(len > 0 && len <= N + 1);
+
+        self.v = [Default::default(); N];
+        self.v_n = Default::default();
+        self.cursor = 0;
+        self.len = len;
+    }
+
+    fn push(&mut self, item: X) {
         if self.cursor == N {
             self.v_n = item;
         } else {
             self.v[self.cursor] = item;
         }
-        self.cursor = (self.cursor + 1) % (N + 1);
+        self.cursor = (self.cursor + 1) % self.len;
     }
 }
 
@@ -47,7 +84,7 @@ where
     type Output = X;
 
     fn index(&self, index: usize) -> &Self::Output {
-        let i = (self.cursor + index) % (N + 1);
+        let i = (self.cursor + index) % self.len;
 
         if i == N {
             &self.v_n
@@ -62,7 +99,7 @@ where
     X: Default + Copy,
 {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        let i = (self.cursor + index) % (N + 1);
+        let i = (self.cursor + index) % self.len;
 
         if i == N {
             &mut self.v_n
@@ -72,6 +109,72 @@ where
     }
 }
 
+// the same over a caller's slice, for when the size is only known at runtime
+pub struct WrappingSlice<'a, X>
+where
+    X: Default + Copy,
+{
+    v: &'a mut [X],
+    cursor: usize,
+    len: usize,
+}
+
+impl<'a, X> WrappingSlice<'a, X>
+where
+    X: Default + Copy,
+{
+    pub fn new(v: &'a mut [X]) -> Self {
+        assert!(!v.is_empty());
+
+        v.fill(Default::default());
+        let len = v.len();
+
+        Self { v, cursor: 0, len }
+    }
+}
+
+impl<X> Ring<X> for WrappingSlice<'_, X>
+where
+    X: Default + Copy,
+{
+    fn capacity(&self) -> usize {
+        self.v.len()
+    }
+
+    fn reset(&mut self, len: usize) {
+        assert!(len > 0 && len <= self.v.len());
+
+        self.v.fill(Default::default());
+        self.cursor = 0;
+        self.len = len;
+    }
+
+    fn push(&mut self, item: X) {
+        self.v[self.cursor] = item;
+        self.cursor = (self.cursor + 1) % self.len;
+    }
+}
+
+impl<X> Index<usize> for WrappingSlice<'_, X>
+where
+    X: Default + Copy,
+{
+    type Output = X;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.v[(self.cursor + index) % self.len]
+    }
+}
+
+impl<X> IndexMut<usize> for WrappingSlice<'_, X>
+where
+    X: Default + Copy,
+{
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.v[(self.cursor + index) % self.len]
+    }
+}
+
 #[test]
 fn test_wrapping_vec() {
     let a: [u8; 6] = [1, 2, 3, 4, 5, 6];
@@ -93,3 +196,56 @@ fn test_wrapping_vec() {
     assert_eq!(wv[4], 9);
     assert_eq!(wv[5], 2);
 }
+
+#[test]
+fn test_wrapping_vec_reset() {
+    let mut iter = core::iter::repeat(7);
+    let mut wv: WrappingVec<u8, 4> = WrappingVec::new(&mut iter);
+
+    wv.reset(3);
+    assert_eq!(wv[0], 0);
+    wv[0] = 1;
+    wv[1] = 2;
+    wv[2] = 3;
+    assert_eq!(wv[3], 1);
+
+    wv.push(4);
+    assert_eq!(wv[0], 2);
+    assert_eq!(wv[1], 3);
+    assert_eq!(wv[2], 4);
+    assert_eq!(wv[3], 2);
+}
+
+#[test]
+fn test_wrapping_vec_short() {
+    let mut iter = [1u8, 2].into_iter();
+    let wv: WrappingVec<u8, 4> = WrappingVec::new(&mut iter);
+
+    assert_eq!(wv[0], 1);
+    assert_eq!(wv[1], 2);
+    assert_eq!(wv[2], 0);
+    assert_eq!(wv[4], 0);
+
+    let wv: WrappingVec<u8, 4> = WrappingVec::new(&mut core::iter::empty());
+    assert_eq!(wv[0], 0);
+}
+
+#[test]
+fn test_wrapping_slice() {
+    let mut buffer = [7u8; 5];
+    let mut ws = WrappingSlice::new(&mut buffer);
+    assert_eq!(ws.capacity(), 5);
+    assert_eq!(ws[4], 0);
+
+    ws.reset(3);
+    ws[0] = 1;
+    ws[1] = 2;
+    ws[2] = 3;
+    assert_eq!(ws[3], 1);
+
+    ws.push(4);
+    assert_eq!(ws[0], 2);
+    assert_eq!(ws[1], 3);
+    assert_eq!(ws[2], 4);
+    assert_eq!(ws[3], 2);
+}